@@ -1,5 +1,84 @@
-use conan_build::Conan;
+use conan_build::{Conan, shell};
+use std::process::Command;
 
 fn main() {
-    Conan::with_host(env!("TARGET").to_owned()).generate_env_source();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "run") {
+        run(&args[1..]);
+    }
+
+    let mut cargo_config = false;
+    let formats = args
+        .into_iter()
+        .filter(|name| match name.as_str() {
+            "cargo" => {
+                cargo_config = true;
+                false
+            }
+            _ => true,
+        })
+        .map(|name| {
+            shell::shell_format(&name).unwrap_or_else(|| {
+                eprintln!(
+                    "Unknown shell format {name:?}, available are sh, envrc, dotenv, fish, nu, bat, ps1 and cargo"
+                );
+                std::process::exit(2);
+            })
+        })
+        .collect::<Vec<_>>();
+    let formats = match formats.is_empty() && !cargo_config {
+        true => shell::DEFAULT_FORMATS.to_vec(),
+        false => formats,
+    };
+
+    let conan = Conan::with_host(env!("TARGET").to_owned());
+    conan
+        .generate_env_source(&formats)
+        .expect("Failure writing the environment scripts");
+    if cargo_config {
+        conan
+            .write_cargo_config(".cargo/config.toml")
+            .expect("Failure writing .cargo/config.toml");
+    }
+}
+
+/// Runs `args` with the environment applied, exiting with its status.
+fn run(args: &[String]) -> ! {
+    let args = match args.first().map(String::as_str) {
+        Some("--") => &args[1..],
+        _ => args,
+    };
+    let Some((program, args)) = args.split_first() else {
+        eprintln!("Usage: genenv run -- <command> [args...]");
+        std::process::exit(2);
+    };
+
+    let conan = Conan::with_host(env!("TARGET").to_owned());
+    let mut command = Command::new(program);
+    command.args(args);
+    conan
+        .env()
+        .and_then(|env| env.apply_to(&mut command))
+        .expect("Failure computing environment");
+
+    exec(command, program)
+}
+
+// Replacing the process leaves exit codes and signals to the command itself
+#[cfg(unix)]
+fn exec(mut command: Command, program: &str) -> ! {
+    use std::os::unix::process::CommandExt;
+
+    let e = command.exec();
+    eprintln!("Failure running {program:?}: {e}");
+    std::process::exit(127);
+}
+
+#[cfg(not(unix))]
+fn exec(mut command: Command, program: &str) -> ! {
+    let status = command.status().unwrap_or_else(|e| {
+        eprintln!("Failure running {program:?}: {e}");
+        std::process::exit(127);
+    });
+    std::process::exit(status.code().unwrap_or(1));
 }
@@ -0,0 +1,88 @@
+use crate::{EnvOp, EnvSet};
+use std::{fs, io, path::Path};
+use toml_edit::{Array, DocumentMut, Item, Table};
+
+/// Entries of a `[target.<triple>]` section of a cargo config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetConfig {
+    pub linker: Option<String>,
+    pub runner: Option<Vec<String>>,
+    pub rustflags: Vec<String>,
+}
+impl TargetConfig {
+    pub fn is_empty(&self) -> bool {
+        self.linker.is_none() && self.runner.is_none() && self.rustflags.is_empty()
+    }
+}
+
+/// Writes the variables set by `env` to `[env]` and the `targets` to their `[target]`
+/// sections, keeping everything else already in the file at `path`.
+pub(crate) fn write(path: &Path, env: &EnvSet, targets: &[(&str, TargetConfig)]) -> io::Result<()> {
+    let mut doc = match fs::read_to_string(path) {
+        Ok(text) => text
+            .parse::<DocumentMut>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(e) => return Err(e),
+    };
+
+    merge(&mut doc, env, targets);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, doc.to_string())
+}
+
+fn merge(doc: &mut DocumentMut, env: &EnvSet, targets: &[(&str, TargetConfig)]) {
+    // Cargo can only set variables, prepending to PATH and such is left to the env scripts
+    let vars = env
+        .iter()
+        .filter_map(|var| match &var.op {
+            EnvOp::Set(value) => Some((var.name.as_str(), value.as_str())),
+            EnvOp::Prepend(_) | EnvOp::Append(_) => None,
+        })
+        .collect::<Vec<_>>();
+    if !vars.is_empty() {
+        let table = table_mut(doc.as_table_mut(), "env");
+        for (name, value) in vars {
+            table[name] = toml_edit::value(value);
+        }
+    }
+
+    for (target, config) in targets.iter().filter(|(_, config)| !config.is_empty()) {
+        let targets = table_mut(doc.as_table_mut(), "target");
+        targets.set_implicit(true);
+        let table = table_mut(targets, target);
+
+        if let Some(linker) = &config.linker {
+            table["linker"] = toml_edit::value(linker.as_str());
+        }
+        if let Some(runner) = &config.runner {
+            table["runner"] = toml_edit::value(runner.iter().collect::<Array>());
+        }
+        if !config.rustflags.is_empty() {
+            let rustflags = table
+                .entry("rustflags")
+                .or_insert_with(|| toml_edit::value(Array::new()))
+                .as_array_mut()
+                .unwrap_or_else(|| panic!("target.{target}.rustflags must be an array"));
+            for flag in &config.rustflags {
+                if !rustflags
+                    .iter()
+                    .any(|existing| existing.as_str() == Some(flag))
+                {
+                    rustflags.push(flag.as_str());
+                }
+            }
+        }
+    }
+}
+
+fn table_mut<'a>(parent: &'a mut Table, key: &str) -> &'a mut Table {
+    parent
+        .entry(key)
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_mut()
+        .unwrap_or_else(|| panic!("{key} must be a table in cargo config"))
+}
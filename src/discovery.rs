@@ -0,0 +1,467 @@
+use crate::{BuildInfo, BuildInfoSet, BUILD_INFO};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+pub struct Discovery {
+    roots: Vec<PathBuf>,
+    max_depth: Option<usize>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    from_env: bool,
+    from_filesystem: bool,
+    duplicates: DuplicatePolicy,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DuplicatePolicy {
+    Warn,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Source {
+    Env(String),
+    Filesystem { distance: usize, root: PathBuf },
+}
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "env {var}"),
+            Source::Filesystem { distance, root } => {
+                write!(f, "filesystem {root:?} (distance {distance})")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Verdict {
+    Accepted { target: &'static str },
+    NotFound,
+    Excluded,
+    SameFile(PathBuf),
+    Unreadable(String),
+    Shadowed { target: &'static str, by: PathBuf },
+}
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Accepted { target } => write!(f, "accepted for {target}"),
+            Verdict::NotFound => write!(f, "not found"),
+            Verdict::Excluded => write!(f, "excluded by glob"),
+            Verdict::SameFile(path) => write!(f, "same file as {path:?}"),
+            Verdict::Unreadable(e) => write!(f, "unreadable: {e}"),
+            Verdict::Shadowed { target, by } => {
+                write!(f, "duplicate for {target}, shadowed by {by:?}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub source: Source,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryReport {
+    pub candidates: Vec<Candidate>,
+    pub watched_dirs: Vec<PathBuf>,
+}
+impl DiscoveryReport {
+    pub fn accepted(&self) -> impl Iterator<Item = &Candidate> {
+        self.candidates
+            .iter()
+            .filter(|candidate| matches!(candidate.verdict, Verdict::Accepted { .. }))
+    }
+
+    pub fn duplicates(&self) -> impl Iterator<Item = &Candidate> {
+        self.candidates
+            .iter()
+            .filter(|candidate| matches!(candidate.verdict, Verdict::Shadowed { .. }))
+    }
+
+    pub fn has_duplicates(&self) -> bool {
+        self.duplicates().next().is_some()
+    }
+}
+impl fmt::Display for DiscoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for candidate in &self.candidates {
+            writeln!(
+                f,
+                "{path:?} from {source}: {verdict}",
+                path = candidate.path,
+                source = candidate.source,
+                verdict = candidate.verdict
+            )?;
+        }
+
+        Ok(())
+    }
+}
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Discovery {
+    pub fn new() -> Discovery {
+        Discovery {
+            roots: Vec::new(),
+            max_depth: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            from_env: true,
+            from_filesystem: true,
+            duplicates: DuplicatePolicy::Warn,
+        }
+    }
+
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    pub fn manifest_dir_root(self) -> Self {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR must be set");
+        self.root(dir)
+    }
+
+    pub fn workspace_root(self) -> Self {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR must be set");
+        let workspace = Self::find_workspace_root(Path::new(&dir)).unwrap_or_else(|| dir.into());
+        self.root(workspace)
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    pub fn include<S: Into<String>>(mut self, glob: S) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    pub fn exclude<S: Into<String>>(mut self, glob: S) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    pub fn env_only(mut self) -> Self {
+        self.from_env = true;
+        self.from_filesystem = false;
+        self
+    }
+
+    pub fn filesystem_only(mut self) -> Self {
+        self.from_env = false;
+        self.from_filesystem = true;
+        self
+    }
+
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    pub fn find_all(&self) -> io::Result<BuildInfoSet> {
+        let mut info = HashMap::new();
+        let mut seen = HashMap::<PathBuf, PathBuf>::new();
+        let mut candidates = Vec::new();
+
+        for (path, source) in self.candidates()? {
+            let verdict = if !self.is_selected(&path) {
+                Verdict::Excluded
+            } else if !path.exists() {
+                Verdict::NotFound
+            } else {
+                let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+                match seen.get(&canonical) {
+                    Some(first) => Verdict::SameFile(first.clone()),
+                    None => {
+                        seen.insert(canonical, path.clone());
+                        match BuildInfo::read_build_info(&path) {
+                            Ok(build_info) => {
+                                let target = build_info.target();
+                                match info.get(target) {
+                                    Some(BuildInfo { path: by, .. }) => Verdict::Shadowed {
+                                        target,
+                                        by: by.clone(),
+                                    },
+                                    None => {
+                                        info.insert(target, build_info);
+                                        Verdict::Accepted { target }
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                crate::warn(&format!("Error opening {path:?}: {e}"));
+                                Verdict::Unreadable(e.to_string())
+                            }
+                        }
+                    }
+                }
+            };
+
+            candidates.push(Candidate {
+                path,
+                source,
+                verdict,
+            });
+        }
+
+        let watched_dirs = self.watched_dirs(&candidates);
+        let report = DiscoveryReport {
+            candidates,
+            watched_dirs,
+        };
+        for duplicate in report.duplicates() {
+            let message = format!(
+                "Conflicting build info {path:?} from {source}: {verdict}",
+                path = duplicate.path,
+                source = duplicate.source,
+                verdict = duplicate.verdict
+            );
+            match self.duplicates {
+                DuplicatePolicy::Warn => crate::warn(&message),
+                DuplicatePolicy::Error => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message))
+                }
+            }
+        }
+
+        Ok(BuildInfoSet { info, report })
+    }
+
+    /// Candidates sorted by precedence: env vars first, then nearer filesystem paths.
+    pub fn candidates(&self) -> io::Result<Vec<(PathBuf, Source)>> {
+        let roots = self.roots()?;
+
+        let mut candidates = Vec::new();
+        if self.from_env {
+            let mut vars = BuildInfoSet::vars_from_env().collect::<Vec<_>>();
+            vars.sort();
+            candidates.extend(vars.into_iter().map(|(var, path)| (path, Source::Env(var))));
+        }
+        if self.from_filesystem {
+            let mut found = Vec::new();
+            for root in &roots {
+                found.extend(
+                    Self::sourced_path_from_filesystem(root, self.max_depth).map(
+                        |(distance, path)| {
+                            let root = root.clone();
+                            (Source::Filesystem { distance, root }, path)
+                        },
+                    ),
+                );
+            }
+            found.sort_by(|(a, a_path), (b, b_path)| a.cmp(b).then_with(|| a_path.cmp(b_path)));
+            candidates.extend(found.into_iter().map(|(source, path)| (path, source)));
+        }
+
+        Ok(candidates)
+    }
+
+    /// Directories holding a found build info that can be watched as a whole. Cargo scans
+    /// watched directories recursively, so only those without subdirectories qualify, such
+    /// as a `conan install` output folder, which changes when the install is rerun.
+    /// Directories that cannot be listed are left out.
+    fn watched_dirs(&self, candidates: &[Candidate]) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        for dir in candidates
+            .iter()
+            .filter(|candidate| {
+                !matches!(
+                    candidate.verdict,
+                    Verdict::NotFound | Verdict::Excluded | Verdict::SameFile(_)
+                )
+            })
+            .filter_map(|candidate| candidate.path.parent())
+        {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let has_subdirs = entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()));
+            if !has_subdirs {
+                dirs.push(dir.to_owned());
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+
+        dirs
+    }
+
+    fn roots(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(match self.roots.is_empty() {
+            true => vec![std::env::current_dir()?],
+            false => self.roots.clone(),
+        })
+    }
+
+    pub fn path_from_filesystem(
+        root: &Path,
+        max_depth: Option<usize>,
+    ) -> impl Iterator<Item = PathBuf> + use<'_> {
+        let mut paths = Self::sourced_path_from_filesystem(root, max_depth).collect::<Vec<_>>();
+        paths.reverse();
+        paths.into_iter().map(|(_, path)| path)
+    }
+
+    fn sourced_path_from_filesystem(
+        root: &Path,
+        max_depth: Option<usize>,
+    ) -> impl Iterator<Item = (usize, PathBuf)> + use<'_> {
+        root.ancestors()
+            .take(max_depth.map_or(usize::MAX, |depth| depth + 1))
+            .enumerate()
+            .flat_map(|(depth, dir)| {
+                let own = (depth, dir.join(BUILD_INFO));
+                let children = dir
+                    .read_dir()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Result::ok)
+                    .map(move |entry| (depth + 1, entry.path().join(BUILD_INFO)));
+
+                [own].into_iter().chain(children)
+            })
+    }
+
+    fn is_selected(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let included =
+            self.include.is_empty() || self.include.iter().any(|glob| glob_match(glob, &path));
+        let excluded = self.exclude.iter().any(|glob| glob_match(glob, &path));
+
+        included && !excluded
+    }
+
+    fn find_workspace_root(manifest_dir: &Path) -> Option<PathBuf> {
+        manifest_dir
+            .ancestors()
+            .filter(|dir| {
+                std::fs::read_to_string(dir.join("Cargo.toml"))
+                    .map(|manifest| manifest.lines().any(|line| line.trim() == "[workspace]"))
+                    .unwrap_or(false)
+            })
+            .last()
+            .map(Path::to_owned)
+    }
+}
+
+fn glob_match(glob: &str, text: &str) -> bool {
+    fn go(glob: &[u8], text: &[u8]) -> bool {
+        match glob {
+            [] => text.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| go(rest, &text[i..])),
+            [b'*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != b'/')
+                .any(|i| go(rest, &text[i..])),
+            [b'?', rest @ ..] => match text {
+                [c, text @ ..] if *c != b'/' => go(rest, text),
+                _ => false,
+            },
+            [g, rest @ ..] => match text {
+                [c, text @ ..] if c == g => go(rest, text),
+                _ => false,
+            },
+        }
+    }
+
+    go(glob.as_bytes(), text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn verdict(report: &DiscoveryReport, dir: &str) -> Verdict {
+        report
+            .candidates
+            .iter()
+            .find(|candidate| candidate.path.parent().unwrap().ends_with(dir))
+            .unwrap_or_else(|| panic!("No candidate in {dir}"))
+            .verdict
+            .clone()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_unlistable_dirs_when_watching() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = TempDir::new("unlistable-dir");
+        dir.write(
+            "locked/conanbuildinfo.json",
+            r#"{"settings": {"os": "Linux", "arch": "x86_64"}, "options": {}, "dependencies": []}"#,
+        );
+        let locked = dir.path().join("locked");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o311)).unwrap();
+        // Root lists the directory anyway
+        let listable = fs::read_dir(&locked).is_ok();
+
+        let set = Discovery::new()
+            .filesystem_only()
+            .root(dir.path())
+            .max_depth(0)
+            .find_all();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let set = set.unwrap();
+        assert_eq!(
+            verdict(set.report(), "locked"),
+            Verdict::Accepted {
+                target: "x86_64-unknown-linux-gnu"
+            }
+        );
+        assert_eq!(set.report().watched_dirs.contains(&locked), listable);
+    }
+
+    #[test]
+    fn records_invalid_build_infos_as_unreadable() {
+        let dir = TempDir::new("invalid-build-infos");
+        dir.write(
+            "valid/conanbuildinfo.json",
+            r#"{"settings": {"os": "Linux", "arch": "x86_64"}, "options": {}, "dependencies": []}"#,
+        );
+        dir.write("malformed/conanbuildinfo.json", "{not json");
+        dir.write(
+            "unsupported/conanbuildinfo.json",
+            r#"{"settings": {"os": "SunOS", "arch": "sparc"}, "dependencies": []}"#,
+        );
+        dir.write("no-settings/conanbuildinfo.json", r#"{"dependencies": []}"#);
+
+        let set = Discovery::new()
+            .filesystem_only()
+            .root(dir.path())
+            .max_depth(0)
+            .find_all()
+            .unwrap();
+
+        let report = set.report();
+        assert_eq!(
+            verdict(report, "valid"),
+            Verdict::Accepted {
+                target: "x86_64-unknown-linux-gnu"
+            }
+        );
+        for invalid in ["malformed", "unsupported", "no-settings"] {
+            assert!(
+                matches!(verdict(report, invalid), Verdict::Unreadable(_)),
+                "{invalid}: {}",
+                verdict(report, invalid)
+            );
+        }
+    }
+}
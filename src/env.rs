@@ -0,0 +1,403 @@
+use crate::{shell, BuildInfoSet, ShellFormat, SysEnvRegistry};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvOp {
+    Set(String),
+    Prepend(Vec<String>),
+    Append(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvVar {
+    pub name: String,
+    pub op: EnvOp,
+}
+
+/// Environment changes computed from the build infos, in the order they must be applied.
+/// Prepend and append entries are joined with the platform path separator when applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvSet {
+    vars: Vec<EnvVar>,
+    watched: Vec<String>,
+}
+impl EnvSet {
+    pub fn new() -> EnvSet {
+        EnvSet::default()
+    }
+
+    /// `build` is the target of the machine running the build, whose build info provides
+    /// the tools.
+    pub fn from_build_info_set(
+        build_info_set: &BuildInfoSet,
+        host: &str,
+        build: &str,
+        registry: &SysEnvRegistry,
+    ) -> io::Result<EnvSet> {
+        let mut targets = build_info_set.all_targets(host).collect::<Vec<_>>();
+        targets.sort_by_key(|(_, info)| info.target());
+        let build = build_info_set.get_current_target(build);
+
+        let mut env = EnvSet::new();
+        for (is_host, info) in targets {
+            info.extend_env(is_host, build, registry, &mut env)?;
+        }
+
+        Ok(env)
+    }
+
+    pub fn set<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        self.push(name.into(), EnvOp::Set(value.into()));
+    }
+
+    pub fn prepend<N, I, V>(&mut self, name: N, entries: I)
+    where
+        N: Into<String>,
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        let entries = entries.into_iter().map(Into::into).collect::<Vec<_>>();
+        if !entries.is_empty() {
+            self.push(name.into(), EnvOp::Prepend(entries));
+        }
+    }
+
+    pub fn append<N, I, V>(&mut self, name: N, entries: I)
+    where
+        N: Into<String>,
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        let entries = entries.into_iter().map(Into::into).collect::<Vec<_>>();
+        if !entries.is_empty() {
+            self.push(name.into(), EnvOp::Append(entries));
+        }
+    }
+
+    /// Records a file the environment was computed from.
+    pub fn watch<P: Into<String>>(&mut self, path: P) {
+        let path = path.into();
+        if !self.watched.contains(&path) {
+            self.watched.push(path);
+        }
+    }
+
+    pub fn watched(&self) -> impl Iterator<Item = &str> {
+        self.watched.iter().map(String::as_str)
+    }
+
+    fn push(&mut self, name: String, op: EnvOp) {
+        self.vars.push(EnvVar { name, op });
+    }
+
+    pub fn extend(&mut self, rhs: EnvSet) {
+        self.vars.extend(rhs.vars);
+        for path in rhs.watched {
+            self.watch(path);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EnvVar> {
+        self.vars.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Final value of every variable touched, starting from the values returned by `current`.
+    pub fn resolve_with<F>(&self, mut current: F) -> io::Result<Vec<(String, OsString)>>
+    where
+        F: FnMut(&str) -> Option<OsString>,
+    {
+        let mut resolved = Vec::<(String, OsString)>::new();
+
+        for var in &self.vars {
+            let previous = match resolved.iter().position(|(name, _)| *name == var.name) {
+                Some(i) => Some(resolved.remove(i).1),
+                None => current(&var.name),
+            };
+            let previous = previous
+                .iter()
+                .flat_map(std::env::split_paths)
+                .filter(|path| !path.as_os_str().is_empty());
+
+            let value = match &var.op {
+                EnvOp::Set(value) => OsString::from(value),
+                EnvOp::Prepend(entries) => {
+                    join_paths(entries.iter().map(PathBuf::from).chain(previous))?
+                }
+                EnvOp::Append(entries) => {
+                    join_paths(previous.chain(entries.iter().map(PathBuf::from)))?
+                }
+            };
+            resolved.push((var.name.clone(), value));
+        }
+
+        Ok(resolved)
+    }
+
+    pub fn resolve(&self) -> io::Result<Vec<(String, OsString)>> {
+        self.resolve_with(|name| std::env::var_os(name))
+    }
+
+    pub fn apply_to(&self, command: &mut Command) -> io::Result<()> {
+        let overrides = command
+            .get_envs()
+            .map(|(name, value)| (name.to_owned(), value.map(ToOwned::to_owned)))
+            .collect::<Vec<_>>();
+
+        let resolved =
+            self.resolve_with(|name| match overrides.iter().find(|(key, _)| key == name) {
+                Some((_, value)) => value.clone(),
+                None => std::env::var_os(name),
+            })?;
+        command.envs(resolved);
+
+        Ok(())
+    }
+
+    pub fn write(&self, format: &dyn ShellFormat, w: &mut dyn io::Write) -> io::Result<()> {
+        let separator = format.path_separator().to_string();
+        let join = |name: &str, entries: &[String]| {
+            let entries = entries
+                .iter()
+                .map(|entry| format.path_entry(entry))
+                .collect::<Vec<_>>();
+            match entries.iter().find(|entry| entry.contains(&separator)) {
+                Some(entry) => Err(invalid_input(format!(
+                    "{entry:?} in {name} contains the path separator {separator:?} of {}",
+                    format.name()
+                ))),
+                None => Ok(entries.join(&separator)),
+            }
+        };
+
+        if let Some(var) = self.vars.iter().find(|var| !is_valid_name(&var.name)) {
+            return Err(invalid_input(format!(
+                "{:?} is not a valid variable name",
+                var.name
+            )));
+        }
+        // Checked before writing anything, so that nothing is left half written
+        let lists = self
+            .vars
+            .iter()
+            .map(|var| match &var.op {
+                EnvOp::Set(_) => Ok(String::new()),
+                EnvOp::Prepend(entries) | EnvOp::Append(entries) => join(&var.name, entries),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        for path in &self.watched {
+            format.watch(w, path)?;
+        }
+        for name in self.names() {
+            format.save(w, name, &shell::saved_name(name))?;
+        }
+        for (var, list) in self.vars.iter().zip(&lists) {
+            let name = var.name.as_str();
+            match &var.op {
+                EnvOp::Set(value) => format.set(w, name, value)?,
+                EnvOp::Prepend(_) => format.prepend(w, name, list)?,
+                EnvOp::Append(_) => format.append(w, name, list)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a script restoring the variables saved by the one from [`EnvSet::write`].
+    pub fn write_deactivate(
+        &self,
+        format: &dyn ShellFormat,
+        w: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        for name in self.names() {
+            format.restore(w, name, &shell::saved_name(name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Names of the variables touched, without repetition.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for var in &self.vars {
+            if !names.contains(&var.name.as_str()) {
+                names.push(var.name.as_str());
+            }
+        }
+
+        names
+    }
+}
+
+pub(crate) fn utf8_path(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{path:?} is not valid UTF-8"),
+        )
+    })
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn join_paths<I: IntoIterator<Item = PathBuf>>(paths: I) -> io::Result<OsString> {
+    std::env::join_paths(paths).map_err(|e| invalid_input(e.to_string()))
+}
+
+impl<'a> IntoIterator for &'a EnvSet {
+    type Item = &'a EnvVar;
+    type IntoIter = std::slice::Iter<'a, EnvVar>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shell, testing::TempDir, BuildInfo, Discovery};
+
+    fn written(env: &EnvSet, format: &dyn ShellFormat) -> io::Result<String> {
+        let mut script = Vec::new();
+        env.write(format, &mut script)?;
+        Ok(String::from_utf8(script).unwrap())
+    }
+
+    fn value_of<'a>(env: &'a EnvSet, name: &str) -> Option<&'a str> {
+        env.iter()
+            .filter(|var| var.name == name)
+            .find_map(|var| match &var.op {
+                EnvOp::Set(value) => Some(value.as_str()),
+                EnvOp::Prepend(_) | EnvOp::Append(_) => None,
+            })
+    }
+
+    #[test]
+    fn takes_tools_from_the_build_context_when_cross_compiling() {
+        let dir = TempDir::new("cross-tools");
+        for (target, os, arch) in [("host", "Android", "armv8"), ("build", "Linux", "x86_64")] {
+            let root = dir.path().join(target).join("protobuf");
+            let root = root.to_str().unwrap();
+            dir.write(
+                &format!("{target}/conanbuildinfo.json"),
+                &format!(
+                    r#"{{"settings": {{"os": "{os}", "arch": "{arch}"}}, "options": {{}},
+                    "dependencies": [{{"name": "protobuf", "version": "3.21.12",
+                    "rootpath": "{root}", "lib_paths": [], "libs": [],
+                    "include_paths": ["{root}/include"], "bin_paths": ["{root}/bin"]}}]}}"#
+                ),
+            );
+        }
+        let set = Discovery::new()
+            .filesystem_only()
+            .root(dir.path())
+            .max_depth(0)
+            .find_all()
+            .unwrap();
+
+        let env = EnvSet::from_build_info_set(
+            &set,
+            "aarch64-linux-android",
+            "x86_64-unknown-linux-gnu",
+            &SysEnvRegistry::builtin(),
+        )
+        .unwrap();
+
+        let host = dir.path().join("host/protobuf/bin/protoc");
+        let build = dir.path().join("build/protobuf/bin/protoc");
+        assert_eq!(value_of(&env, "PROTOC"), build.to_str());
+        assert_eq!(
+            value_of(&env, "X86_64_UNKNOWN_LINUX_GNU_PROTOC"),
+            build.to_str()
+        );
+        assert_eq!(
+            value_of(&env, "AARCH64_LINUX_ANDROID_PROTOC"),
+            host.to_str()
+        );
+
+        let path = env
+            .iter()
+            .filter(|var| var.name == "PATH")
+            .flat_map(|var| match &var.op {
+                EnvOp::Prepend(entries) | EnvOp::Append(entries) => entries.clone(),
+                EnvOp::Set(value) => vec![value.clone()],
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(path, [build.parent().unwrap().to_str().unwrap()]);
+    }
+
+    #[test]
+    fn converts_windows_paths_in_colon_lists() {
+        let mut env = EnvSet::new();
+        env.prepend("PATH", ["C:\\conan\\bin", "/usr/bin"]);
+
+        let script = written(&env, &shell::Sh).unwrap();
+        assert!(
+            script.contains("export PATH='/c/conan/bin:/usr/bin'"),
+            "{script}"
+        );
+        let script = written(&env, &shell::PowerShell).unwrap();
+        assert!(script.contains("C:\\conan\\bin;/usr/bin"), "{script}");
+    }
+
+    #[test]
+    fn rejects_entries_containing_the_separator_before_writing() {
+        let mut env = EnvSet::new();
+        env.set("FIRST", "1");
+        env.prepend("PATH", ["/opt/a:b"]);
+
+        let mut script = Vec::new();
+        let error = env.write(&shell::Sh, &mut script).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(script.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extend_env_rejects_non_utf8_paths() {
+        use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+        let mut name = b"conan-build-test-\xff-".to_vec();
+        name.extend_from_slice(std::process::id().to_string().as_bytes());
+        let dir = std::env::temp_dir().join(OsStr::from_bytes(&name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("build-info.json");
+        fs::write(
+            &path,
+            r#"{"settings": {"os": "Linux", "arch": "x86_64"}, "options": {}, "dependencies": []}"#,
+        )
+        .unwrap();
+
+        let info = BuildInfo::read_build_info(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut env = EnvSet::new();
+        let error = info
+            .unwrap()
+            .extend_env(true, None, &SysEnvRegistry::new(), &mut env)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(env.is_empty());
+        assert!(utf8_path(&path).is_err());
+    }
+}
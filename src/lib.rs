@@ -1,54 +1,62 @@
+mod cargo_config;
+mod discovery;
+mod env;
+mod metadata;
+mod rust_module;
+pub mod shell;
+pub mod sys_env;
+mod version;
+
+pub use cargo_config::TargetConfig;
+pub use discovery::{Candidate, Discovery, DiscoveryReport, DuplicatePolicy, Source, Verdict};
+pub use env::{EnvOp, EnvSet, EnvVar};
+pub use metadata::{Metadata, PackageConfig};
 use serde_json::Value;
+pub use shell::ShellFormat;
 use std::{
     collections::HashMap,
+    fmt,
     fs::File,
-    io,
+    io::{self, Write},
     ops::Deref,
     path::{Path, PathBuf},
+    str::FromStr,
 };
+pub use sys_env::SysEnvRegistry;
+pub use version::{Version, VersionReq};
 
 const BUILD_INFO: &str = "conanbuildinfo.json";
 
 pub struct BuildInfoSet {
     info: HashMap<&'static str, BuildInfo>,
+    report: DiscoveryReport,
 }
 impl BuildInfoSet {
     pub fn find_all() -> io::Result<Self> {
-        let current_dir = std::env::current_dir()?;
-        let info = Self::path_from_filesystem(&current_dir)
-            .chain(Self::path_from_env())
-            .filter(|path| path.exists())
-            .map(|path| BuildInfo::read_build_info(&path).map_err(|e| (path, e)))
-            .filter_map(|r| {
-                r.map(|info| (info.target(), info))
-                    .map_err(|(path, e)| eprintln!("Error opening {path:?}: {e}"))
-                    .ok()
-            })
-            .collect();
-
-        Ok(Self { info })
+        Discovery::new().find_all()
     }
 
     pub fn path_from_env() -> impl Iterator<Item = PathBuf> {
-        std::env::vars().filter_map(|(key, path)| match key.split('_').next_back() {
-            Some("CONANBUILDINFO") => Some(path.into()),
-            None if key == "CONANBUILDINFO" => Some(path.into()),
-            _ => None,
+        Self::vars_from_env().map(|(_, path)| path)
+    }
+
+    pub fn vars_from_env() -> impl Iterator<Item = (String, PathBuf)> {
+        std::env::vars_os().filter_map(|(key, path)| {
+            let key = key.into_string().ok()?;
+            match key.split('_').next_back() {
+                Some("CONANBUILDINFO") => Some((key, path.into())),
+                None if key == "CONANBUILDINFO" => Some((key, path.into())),
+                _ => None,
+            }
         })
     }
 
+    pub fn report(&self) -> &DiscoveryReport {
+        &self.report
+    }
+
     pub fn path_from_filesystem(current_dir: &Path) -> impl Iterator<Item = PathBuf> + use<'_> {
-        current_dir
-            .ancestors()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .flat_map(|dir| {
-                dir.read_dir()
-                    .unwrap()
-                    .map(|path| path.expect("read dir may not fail").path().join(BUILD_INFO))
-                    .chain([dir.join(BUILD_INFO)])
-            })
+        Discovery::path_from_filesystem(current_dir, None)
     }
 
     pub fn get_current_target(&self, host: &str) -> Option<&BuildInfo> {
@@ -76,31 +84,90 @@ impl BuildInfoSet {
 pub struct BuildInfo {
     path: PathBuf,
     info: HashMap<String, Value>,
-    libs: HashMap<String, Link>,
+    libs: HashMap<String, HashMap<String, Vec<LibFile>>>,
     settings: Value,
+    options: HashMap<String, HashMap<String, OptionValue>>,
+    env_info: EnvSet,
+    buildenv_info: EnvSet,
+    runenv_info: EnvSet,
+    user_info: HashMap<String, HashMap<String, String>>,
 }
 impl BuildInfo {
+    /// Fails on files that are not build infos, or whose settings name no supported target.
     pub fn read_build_info<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
         let info: Value = serde_json::from_str(&std::fs::read_to_string(path.as_ref())?)
-            .expect("Invalid build info json");
+            .map_err(|e| invalid(format!("Invalid build info json: {e}")))?;
 
         let settings = info["settings"].clone();
+        let (Some(arch), Some(os)) = (settings["arch"].as_str(), settings["os"].as_str()) else {
+            return Err(invalid("Missing os or arch setting".to_string()));
+        };
+        if Self::target_from_arch_and_os(arch, os).is_none() {
+            return Err(invalid(format!("Unsupported architecture {arch:?}/{os:?}")));
+        }
+        let has_dependencies = info["dependencies"]
+            .as_array()
+            .is_some_and(|deps| deps.iter().all(|dep| dep["name"].is_string()));
+        if !has_dependencies {
+            return Err(invalid("Missing or unnamed dependencies".to_string()));
+        }
+
+        let options = crate::package_options(&info["options"]);
+        let env_info = crate::env_info(&info, "deps_env_info", "env_info");
+        let buildenv_info = crate::env_info(&info, "buildenv_info", "buildenv_info");
+        let runenv_info = crate::env_info(&info, "runenv_info", "runenv_info");
+        let user_info = crate::user_info(&info);
         let info = crate::build_info(&info);
         let libs = crate::find_all_libs(info.iter())?;
 
+        let mut providers = HashMap::<&str, Vec<&str>>::new();
+        for (package, package_libs) in &libs {
+            for lib in package_libs.keys() {
+                providers.entry(lib).or_default().push(package);
+            }
+        }
+        for (lib, mut packages) in providers {
+            if packages.len() > 1 {
+                packages.sort();
+                crate::warn(&format!(
+                    "library {lib:?} is provided by multiple packages {packages:?}"
+                ));
+            }
+        }
+
         Ok(Self {
             path: path.as_ref().to_owned(),
             info,
             libs,
             settings,
+            options,
+            env_info,
+            buildenv_info,
+            runenv_info,
+            user_info,
         })
     }
 
     pub fn target(&self) -> &'static str {
+        // Checked when reading the build info
         Self::target_from_arch_and_os(
             self.settings["arch"].as_str().unwrap(),
             self.settings["os"].as_str().unwrap(),
         )
+        .unwrap()
+    }
+
+    pub fn setting(&self, setting: &str) -> Option<&str> {
+        self.settings.get(setting)?.as_str()
+    }
+
+    pub fn os(&self) -> &str {
+        self.settings["os"].as_str().unwrap()
+    }
+
+    pub fn is_elf(&self) -> bool {
+        matches!(self.os(), "Linux" | "Android" | "FreeBSD")
     }
 
     pub fn all_deps(&self) -> impl Iterator<Item = &str> + Clone {
@@ -118,25 +185,188 @@ impl BuildInfo {
     }
 
     pub fn get_depends_on_package(&self, package: &str) -> DependsOn {
+        self.get_depends_on_configured(package, &PackageConfig::default())
+    }
+
+    pub fn get_depends_on_configured(&self, package: &str, config: &PackageConfig) -> DependsOn {
+        let group = config.group && self.is_elf();
+        let mut grouped = Vec::new();
+
         let libs = self
             .libs_for(package)
             .into_iter()
-            .map(|name| Lib {
-                is_static: !self.is_shared(name),
-                name: name.to_string(),
+            .filter(|name| {
+                config
+                    .components
+                    .as_ref()
+                    .is_none_or(|components| components.iter().any(|c| c == name))
+            })
+            .map(|name| {
+                let link = self.resolve_link(package, name, config.link);
+                let modifiers = config.modifiers_for(name);
+                let file = self
+                    .lib_files(package, name)
+                    .iter()
+                    .find(|file| file.link == link);
+
+                // The libraries are still emitted with rustc-link-lib, as the group args do
+                // not reach the crates depending on this one
+                if let (true, Link::Static, Some(file)) = (group, link, file) {
+                    grouped.push(file.path.clone());
+                }
+
+                let file_name = match modifiers.verbatim {
+                    Some(true) => file
+                        .and_then(|file| file.path.file_name())
+                        .map(|file_name| file_name.to_string_lossy().into_owned()),
+                    _ => None,
+                };
+
+                Lib {
+                    is_static: link == Link::Static,
+                    name: file_name.unwrap_or_else(|| name.to_string()),
+                    modifiers,
+                }
             })
             .collect();
+        let groups = match grouped.is_empty() {
+            true => Vec::new(),
+            false => vec![LinkGroup(grouped)],
+        };
         let libdirs = self
             .libdir_for(package)
             .into_iter()
             .map(|dir| LibDir(dir.to_string()))
             .collect();
+        let files = self.lib_files_for(package);
+
+        DependsOn {
+            libs,
+            groups,
+            libdirs,
+            files,
+        }
+    }
+
+    pub fn resolve_link(&self, package: &str, lib: &str, policy: Option<LinkPolicy>) -> Link {
+        let available = self.lib_variants(package, lib);
+        let fallback = self.default_link(package, lib);
+        let Some(policy) = policy else {
+            return fallback;
+        };
+
+        let (preferred, required) = match policy {
+            LinkPolicy::PreferStatic => (Link::Static, false),
+            LinkPolicy::PreferShared => (Link::Shared, false),
+            LinkPolicy::RequireStatic => (Link::Static, true),
+            LinkPolicy::RequireShared => (Link::Shared, true),
+            LinkPolicy::FromOptions => match self.is_package_shared(package) {
+                Some(true) => (Link::Shared, true),
+                Some(false) => (Link::Static, true),
+                None => return fallback,
+            },
+        };
+
+        if available.contains(&preferred) {
+            preferred
+        } else if required {
+            panic!(
+                "{preferred:?} library {lib:?} required by {policy:?} is not available in {package:?} lib paths {:?}",
+                self.libdir_for(package)
+            )
+        } else {
+            available.first().copied().unwrap_or(fallback)
+        }
+    }
+
+    pub fn options_for(&self, package: &str) -> Option<&HashMap<String, OptionValue>> {
+        self.options.get(package)
+    }
+
+    pub fn option(&self, package: &str, option: &str) -> Option<&OptionValue> {
+        self.options_for(package)?.get(option)
+    }
+
+    pub fn is_package_shared(&self, package: &str) -> Option<bool> {
+        self.option(package, "shared")?.as_bool()
+    }
+
+    pub fn lib_files(&self, package: &str, lib: &str) -> &[LibFile] {
+        self.libs
+            .get(package)
+            .and_then(|libs| libs.get(lib))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn lib_variants(&self, package: &str, lib: &str) -> Vec<Link> {
+        let mut variants = self
+            .lib_files(package, lib)
+            .iter()
+            .map(|file| file.link)
+            .collect::<Vec<_>>();
+        variants.dedup();
+
+        variants
+    }
+
+    pub fn resolve_lib_file(
+        &self,
+        package: &str,
+        lib: &str,
+        policy: Option<LinkPolicy>,
+    ) -> Option<&LibFile> {
+        let link = self.resolve_link(package, lib, policy);
+        self.lib_files(package, lib)
+            .iter()
+            .find(|file| file.link == link)
+    }
 
-        DependsOn { libs, libdirs }
+    pub fn lib_providers(&self, lib: &str) -> Vec<&str> {
+        let mut packages = self
+            .libs
+            .iter()
+            .filter(|(_, libs)| libs.contains_key(lib))
+            .map(|(package, _)| package.as_str())
+            .collect::<Vec<_>>();
+        packages.sort();
+
+        packages
+    }
+
+    /// Link kind used for `lib` when no policy is set: the variant matching the package's
+    /// Conan `shared` option if present, otherwise static over shared. Libraries not found
+    /// on disk, such as system libraries, are linked as shared.
+    pub fn default_link(&self, package: &str, lib: &str) -> Link {
+        let variants = self.lib_variants(package, lib);
+
+        let from_option = self
+            .is_package_shared(package)
+            .map(|shared| match shared {
+                true => Link::Shared,
+                false => Link::Static,
+            })
+            .filter(|link| variants.contains(link));
+
+        from_option
+            .or_else(|| variants.first().copied())
+            .unwrap_or(Link::Shared)
+    }
+
+    /// The default link of the package's libraries, when they all agree.
+    pub fn default_package_link(&self, package: &str) -> Option<Link> {
+        let mut links = self
+            .libs_for(package)
+            .into_iter()
+            .map(|lib| self.default_link(package, lib));
+        let link = links.next()?;
+        links.all(|other| other == link).then_some(link)
     }
 
     pub fn is_shared(&self, lib: &str) -> bool {
-        self.libs.get(lib).copied().unwrap_or(Link::Shared) == Link::Shared
+        let package = self.lib_providers(lib).first().copied().unwrap_or_default();
+
+        self.default_link(package, lib) == Link::Shared
     }
 
     pub fn libdir_for(&self, package: &str) -> Vec<&str> {
@@ -151,6 +381,14 @@ impl BuildInfo {
             .map(|lib| lib.as_str().unwrap())
     }
 
+    pub fn lib_files_for(&self, package: &str) -> Vec<PathBuf> {
+        self.libs_for(package)
+            .into_iter()
+            .flat_map(|lib| self.lib_files(package, lib))
+            .map(|file| file.path.clone())
+            .collect()
+    }
+
     pub fn libs_for(&self, package: &str) -> Vec<&str> {
         self.package(package)["libs"]
             .as_array()
@@ -178,6 +416,98 @@ impl BuildInfo {
             .collect()
     }
 
+    pub fn tool_path(&self, package: &str, tool: &str) -> Option<PathBuf> {
+        let exe = format!("{tool}.exe");
+        let names = match self.os() {
+            "Windows" => vec![exe.as_str(), tool],
+            _ => vec![tool],
+        };
+
+        self.bindir_for(package)
+            .into_iter()
+            .flat_map(|dir| names.iter().map(move |name| Path::new(dir).join(name)))
+            .find(|path| path.is_file())
+    }
+
+    pub fn license_for(&self, package: &str) -> Vec<&str> {
+        match &self.package(package)["license"] {
+            Value::String(license) => vec![license.as_str()],
+            Value::Array(licenses) => licenses.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn version_for(&self, package: &str) -> Version {
+        Version::parse(
+            self.package(package)["version"]
+                .as_str()
+                .unwrap_or_else(|| panic!("No version for {package:?} in conan info")),
+        )
+    }
+
+    /// `rustc-cfg` flags describing `package`: `conan_<package>`, its version and options.
+    pub fn cfgs_for(&self, package: &str) -> Vec<Cfg> {
+        if self.try_package(package).is_none() {
+            return Vec::new();
+        }
+
+        let prefix = Cfg::ident(&format!("conan_{package}"));
+        let mut cfgs = vec![
+            Cfg {
+                name: prefix.clone(),
+                value: None,
+            },
+            Cfg {
+                name: format!("{prefix}_version"),
+                value: Some(self.version_for(package).to_string()),
+            },
+        ];
+
+        let mut options = self
+            .options_for(package)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        options.sort_by_key(|(option, _)| option.as_str());
+        for (option, value) in options {
+            let name = Cfg::ident(&format!("{prefix}_option_{option}"));
+            match value {
+                OptionValue::Bool(true) => cfgs.push(Cfg { name, value: None }),
+                OptionValue::Value(value) => cfgs.push(Cfg {
+                    name,
+                    value: Some(value.clone()),
+                }),
+                OptionValue::Bool(false) | OptionValue::None => {}
+            }
+        }
+
+        cfgs
+    }
+
+    /// `rustc-check-cfg` declarations for every cfg [`BuildInfo::cfgs_for`] may emit for
+    /// `package`, including those of missing packages and of options currently off. Options
+    /// accept any value, as their cfg is a bare name or has a value depending on the setting.
+    pub fn check_cfgs_for(&self, package: &str) -> Vec<CheckCfg> {
+        let prefix = Cfg::ident(&format!("conan_{package}"));
+        let mut check_cfgs = vec![
+            CheckCfg::name(&prefix),
+            CheckCfg::values(&format!("{prefix}_version")),
+        ];
+
+        let mut options = self
+            .options_for(package)
+            .into_iter()
+            .flat_map(HashMap::keys)
+            .collect::<Vec<_>>();
+        options.sort();
+        for option in options {
+            let name = Cfg::ident(&format!("{prefix}_option_{option}"));
+            check_cfgs.push(CheckCfg::values(&name));
+        }
+
+        check_cfgs
+    }
+
     pub fn rootpath_for(&self, package: &str) -> &str {
         self.package(package)["rootpath"].as_str().unwrap()
     }
@@ -191,74 +521,167 @@ impl BuildInfo {
         self.info.get(package)
     }
 
-    pub fn write_env_source<W1, W2>(&self, is_host: bool, mut sh: W1, mut ps1: W2) -> io::Result<()>
-    where
-        W1: io::Write,
-        W2: io::Write,
-    {
-        let prefix = self.target().replace('-', "_");
+    /// Variables from Conan 1 `env_info` of the dependencies.
+    pub fn env_info(&self) -> &EnvSet {
+        &self.env_info
+    }
 
-        writeln!(
-            sh,
-            "export {prefix}_CONANBUILDINFO={}",
-            self.path.to_string_lossy()
-        )?;
-        writeln!(
-            ps1,
-            "$env:{prefix}_CONANBUILDINFO=\"{}\"",
-            self.path.to_string_lossy()
-        )?;
-
-        let shared_deps = self.all_deps().filter(|package| {
-            self.libs_for(package)
-                .into_iter()
-                .any(|lib| self.is_shared(lib))
-        });
+    /// Variables from Conan 2 `buildenv_info` of the dependencies.
+    pub fn buildenv_info(&self) -> &EnvSet {
+        &self.buildenv_info
+    }
 
-        let libdirs = shared_deps
-            .clone()
-            .flat_map(|package| self.libdir_for(package).into_iter())
-            .collect::<Vec<_>>()
-            .join(":");
+    /// Variables from Conan 2 `runenv_info` of the dependencies.
+    pub fn runenv_info(&self) -> &EnvSet {
+        &self.runenv_info
+    }
 
-        if !libdirs.is_empty() && is_host {
-            writeln!(sh, "export LD_LIBRARY_PATH={libdirs}")?;
-        }
-        let bindirs = shared_deps
-            .flat_map(|package| self.bindir_for(package))
-            .collect::<Vec<_>>()
-            .join(";")
-            .replace('\\', "\\\\");
+    pub fn user_info(&self, package: &str) -> Option<&HashMap<String, String>> {
+        self.user_info.get(package)
+    }
 
-        if !bindirs.is_empty() && is_host {
-            writeln!(ps1, "$env:PATH=\"{bindirs};$env:PATH\"")?;
+    /// `user_info` of all packages as `CONAN_USER_{PACKAGE}_{KEY}` variables.
+    pub fn user_info_env(&self) -> EnvSet {
+        let mut packages = self.user_info.iter().collect::<Vec<_>>();
+        packages.sort_by_key(|(package, _)| *package);
+
+        let mut env = EnvSet::new();
+        for (package, values) in packages {
+            let mut values = values.iter().collect::<Vec<_>>();
+            values.sort();
+            for (key, value) in values {
+                let name = format!("CONAN_USER_{}_{}", Cfg::ident(package), Cfg::ident(key));
+                env.set(name.to_uppercase(), value.as_str());
+            }
         }
 
-        if self.try_package("openssl").is_some() {
-            let openssl_dir = self.rootpath_for("openssl");
-            writeln!(sh, "export {prefix}_OPENSSL_DIR={openssl_dir}",)?;
-            writeln!(ps1, "$env:{prefix}_OPENSSL_DIR=\"{openssl_dir}\"")?;
+        env
+    }
+
+    /// Taken on its own, the build info is also the build context its tools come from.
+    pub fn env(&self, is_host: bool) -> io::Result<EnvSet> {
+        let mut env = EnvSet::new();
+        self.extend_env(is_host, Some(self), &SysEnvRegistry::builtin(), &mut env)?;
+        Ok(env)
+    }
 
-            if is_host {
-                writeln!(sh, "export OPENSSL_DIR={openssl_dir}",)?;
-                writeln!(ps1, "$env:OPENSSL_DIR=\"{openssl_dir}\"")?;
+    /// Tool variables of the host are taken from `build`, the build info of the machine
+    /// running the build. Fails when the build info path is not valid UTF-8, rather than
+    /// writing a lossy one.
+    pub fn extend_env(
+        &self,
+        is_host: bool,
+        build: Option<&BuildInfo>,
+        registry: &SysEnvRegistry,
+        env: &mut EnvSet,
+    ) -> io::Result<()> {
+        let path = env::utf8_path(&self.path)?;
+        let prefix = self.target().replace('-', "_");
+        let mut deps = self.all_deps().collect::<Vec<_>>();
+        deps.sort_unstable();
+
+        env.watch(path);
+        env.set(format!("{prefix}_CONANBUILDINFO"), path);
+
+        if is_host {
+            let mut runtime_dirs = Vec::new();
+            for &package in deps.iter().filter(|package| {
+                self.libs_for(package)
+                    .into_iter()
+                    .any(|lib| self.default_link(package, lib) == Link::Shared)
+            }) {
+                let dirs = match self.os() {
+                    "Windows" => self.bindir_for(package),
+                    _ => self.libdir_for(package),
+                };
+                push_unique(&mut runtime_dirs, dirs);
             }
+            let mut path_dirs = Vec::new();
+            for var in self.runtime_path_vars() {
+                match *var {
+                    "PATH" => push_unique(&mut path_dirs, runtime_dirs.iter().copied()),
+                    _ => env.prepend(*var, runtime_dirs.iter().copied()),
+                }
+            }
+            // Only the binaries of the build context can run on the machine using the scripts
+            if let Some(build) = build {
+                let mut tools = build.all_deps().collect::<Vec<_>>();
+                tools.sort_unstable();
+                for package in tools {
+                    push_unique(&mut path_dirs, build.bindir_for(package));
+                }
+            }
+            env.prepend("PATH", path_dirs);
+
+            env.extend(self.env_info.clone());
+            env.extend(self.buildenv_info.clone());
+            env.extend(self.runenv_info.clone());
+            env.extend(self.user_info_env());
         }
 
-        sh.flush()?;
-        ps1.flush()?;
+        let prefix = prefix.to_uppercase();
+        for &package in &deps {
+            let link = self.default_package_link(package);
+            for (name, value) in registry.resolve(self, Some(self), package, link) {
+                env.set(format!("{prefix}_{name}"), value);
+            }
+        }
+
+        if is_host {
+            // Tools may come from packages only the build context has
+            let mut packages = deps;
+            for package in build.into_iter().flat_map(BuildInfo::all_deps) {
+                if !packages.contains(&package) {
+                    packages.push(package);
+                }
+            }
+            for package in packages {
+                let link = self
+                    .try_package(package)
+                    .and_then(|_| self.default_package_link(package));
+                for (name, value) in registry.resolve(self, build, package, link) {
+                    env.set(name, value);
+                }
+            }
+        }
 
         Ok(())
     }
 
-    pub fn libcxx(&self) -> Option<Lib> {
-        self.libcxx_name().map(|name| Lib {
-            is_static: false,
-            name: name.to_string(),
-        })
+    /// Variables the dynamic loader of the target OS searches for shared libraries.
+    pub fn runtime_path_vars(&self) -> &'static [&'static str] {
+        match self.os() {
+            "Windows" => &["PATH"],
+            "Macos" | "iOS" | "tvOS" | "watchOS" => {
+                &["DYLD_LIBRARY_PATH", "DYLD_FALLBACK_LIBRARY_PATH"]
+            }
+            _ => &["LD_LIBRARY_PATH"],
+        }
+    }
+
+    pub fn write_env_source(
+        &self,
+        is_host: bool,
+        format: &dyn ShellFormat,
+        w: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        self.env(is_host)?.write(format, w)?;
+        w.flush()
     }
 
-    fn libcxx_name(&self) -> Option<&str> {
+    /// The C++ standard library, `None` for MSVC, when unset or when not a known variant.
+    pub fn libcxx(&self) -> Option<LibCxx> {
+        self.libcxx_setting()?.parse().ok()
+    }
+
+    fn libcxx_setting(&self) -> Option<&str> {
+        if matches!(
+            self.settings["compiler"].as_str(),
+            Some("msvc" | "Visual Studio")
+        ) {
+            return None;
+        }
+
         let libcxx = self
             .settings
             .as_object()
@@ -267,50 +690,108 @@ impl BuildInfo {
             .as_str()
             .expect("compiler.libcxx attribute is an string");
 
-        Some(match libcxx {
-            "libstdc++11" => "stdc++",
-            x if x.starts_with("lib") => &x[3..],
-            x => x,
-        })
+        Some(libcxx)
+    }
+
+    /// Libraries for the C++ standard library. Unknown variants, such as the legacy Android
+    /// `gnustl_shared`, are linked as a shared library named after the setting.
+    pub fn libcxx_libs(&self, link: Option<Link>) -> Vec<Lib> {
+        let Some(setting) = self.libcxx_setting() else {
+            return Vec::new();
+        };
+
+        match setting.parse::<LibCxx>() {
+            Ok(libcxx) => libcxx.libs(link.unwrap_or(libcxx.default_link())),
+            Err(e) => {
+                warn(&format!("{e}, linking it by name"));
+                vec![Lib {
+                    is_static: false,
+                    name: setting.strip_prefix("lib").unwrap_or(setting).to_string(),
+                    modifiers: LinkModifiers::default(),
+                }]
+            }
+        }
+    }
+
+    /// Cargo configuration for this target derived from the settings, when built from `host`.
+    pub fn target_config(&self, host: &str) -> TargetConfig {
+        let target = self.target();
+        let host_is_windows = host.contains("-windows-");
+        let mut config = TargetConfig::default();
+
+        if let ("Android", Some(api_level)) = (self.os(), self.setting("os.api_level")) {
+            let triple = match target {
+                "armv7-linux-androideabi" => "armv7a-linux-androideabi",
+                target => target,
+            };
+            let extension = match host_is_windows {
+                true => ".cmd",
+                false => "",
+            };
+            config.linker = Some(format!("{triple}{api_level}-clang{extension}"));
+        }
+
+        if self.os() == "Windows" && !host_is_windows {
+            config.runner = Some(vec!["wine".to_string()]);
+        }
+
+        if let Some("MT" | "MTd" | "static") = self.setting("compiler.runtime") {
+            config
+                .rustflags
+                .push("-Ctarget-feature=+crt-static".to_string());
+        }
+
+        config
     }
 
-    fn target_from_arch_and_os(arch: &str, os: &str) -> &'static str {
-        match os {
+    fn target_from_arch_and_os(arch: &str, os: &str) -> Option<&'static str> {
+        let target = match os {
             "Linux" => match arch {
                 "x86_64" => "x86_64-unknown-linux-gnu",
                 "x86" => "i686-unknown-linux-gnu",
-                arch => unimplemented!("Unsupported architecture {arch:?}/{os:?}"),
+                _ => return None,
             },
             "Windows" => match arch {
                 "x86_64" => "x86_64-pc-windows-msvc",
                 "x86" => "i686-pc-windows-msvc",
-                arch => unimplemented!("Unsupported architecture {arch:?}/{os:?}"),
+                _ => return None,
             },
             "Macos" => match arch {
                 "armv8" => "aarch64-apple-darwin",
                 "x86_64" => "x86_64-apple-darwin",
-                arch => unimplemented!("Unsupported architecture {arch:?}/{os:?}"),
+                _ => return None,
             },
             "iOS" => match arch {
                 "armv8" => "aarch64-apple-ios",
-                arch => unimplemented!("Unsupported architecture {arch:?}/{os:?}"),
+                _ => return None,
             },
             "Android" => match arch {
                 "armv8" => "aarch64-linux-android",
                 "armv7" => "armv7-linux-androideabi",
                 "x86" => "i686-linux-android",
                 "x86_64" => "x86_64-linux-android",
-                arch => unimplemented!("Unsupported architecture {arch:?}/{os:?}"),
+                _ => return None,
             },
-            os => unimplemented!("Unsupported OS {os:?}"),
-        }
+            _ => return None,
+        };
+
+        Some(target)
     }
 }
 
+pub fn build() {
+    let metadata = Metadata::from_env().expect("Failure reading Cargo.toml");
+    Conan::new().apply_metadata(&metadata);
+}
+
 pub struct Conan {
     build_info_set: BuildInfoSet,
     host: String,
+    build: String,
     rerun_if_changed: bool,
+    packages: HashMap<String, PackageConfig>,
+    linked: Vec<String>,
+    sys_env: SysEnvRegistry,
 }
 impl Default for Conan {
     fn default() -> Self {
@@ -320,21 +801,54 @@ impl Default for Conan {
 impl Conan {
     pub fn new() -> Conan {
         let host = std::env::var("TARGET").expect("TARGET variable must be set");
-        Self::with_host(host)
+        let build = std::env::var("HOST").unwrap_or_else(|_| host.clone());
+        Self::with_host_and_build(host, build)
     }
 
     pub fn with_host(host: String) -> Conan {
+        let build = host.clone();
+        Self::with_host_and_build(host, build)
+    }
+
+    pub fn with_host_and_build(host: String, build: String) -> Conan {
         let build_info_set = BuildInfoSet::find_all().expect("Failure reading conanbuildinfo");
+        Self::with_build_info_set(build_info_set, host, build)
+    }
+
+    pub fn with_discovery(discovery: &Discovery) -> Conan {
+        let host = std::env::var("TARGET").expect("TARGET variable must be set");
+        let build = std::env::var("HOST").unwrap_or_else(|_| host.clone());
+        let build_info_set = discovery
+            .find_all()
+            .expect("Failure reading conanbuildinfo");
+        Self::with_build_info_set(build_info_set, host, build)
+    }
 
+    pub fn with_build_info_set(build_info_set: BuildInfoSet, host: String, build: String) -> Conan {
         eprintln!("Targets:");
         for (target, path) in build_info_set.targets_and_paths() {
             eprintln!("    {target}: {}", path.to_string_lossy());
         }
+        eprintln!("Discovered:");
+        for candidate in build_info_set.report().candidates.iter() {
+            if candidate.verdict != Verdict::NotFound {
+                eprintln!(
+                    "    {path:?} from {source}: {verdict}",
+                    path = candidate.path,
+                    source = candidate.source,
+                    verdict = candidate.verdict
+                );
+            }
+        }
 
         Conan {
             build_info_set,
             host,
+            build,
             rerun_if_changed: false,
+            packages: HashMap::new(),
+            linked: Vec::new(),
+            sys_env: SysEnvRegistry::builtin(),
         }
     }
 
@@ -344,11 +858,48 @@ impl Conan {
         }
 
         self.rerun_if_changed = true;
-        let build_info = self.build_info();
-        println!(
-            "cargo:rerun-if-changed={path}",
-            path = build_info.path.to_string_lossy()
-        );
+
+        let report = self.build_info_set.report();
+        let mut vars = ["TARGET", "HOST", "CONANBUILDINFO"]
+            .into_iter()
+            .map(str::to_string)
+            .chain([&self.host, &self.build].into_iter().map(|target| {
+                let prefix = target.replace('-', "_");
+                format!("{prefix}_CONANBUILDINFO")
+            }))
+            .chain(
+                report
+                    .candidates
+                    .iter()
+                    .filter_map(|candidate| match &candidate.source {
+                        Source::Env(var) => Some(var.clone()),
+                        Source::Filesystem { .. } => None,
+                    }),
+            )
+            .collect::<Vec<_>>();
+        vars.sort();
+        vars.dedup();
+        for var in vars {
+            println!("cargo:rerun-if-env-changed={var}");
+        }
+
+        for candidate in &report.candidates {
+            if candidate.path.exists() {
+                println!(
+                    "cargo:rerun-if-changed={path}",
+                    path = candidate.path.to_string_lossy()
+                );
+            }
+        }
+        for dir in &report.watched_dirs {
+            println!("cargo:rerun-if-changed={dir}", dir = dir.to_string_lossy());
+        }
+    }
+
+    fn mark_rerun_if_lib_changed(depends_on: &DependsOn) {
+        for path in &depends_on.files {
+            println!("cargo:rerun-if-changed={}", path.to_string_lossy());
+        }
     }
 
     pub fn build_info(&self) -> &BuildInfo {
@@ -363,45 +914,391 @@ impl Conan {
             })
     }
 
+    pub fn build_context_info(&self) -> &BuildInfo {
+        self.build_info_set
+            .get_current_target(&self.build)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Could not find build context info for {:?}, available are: {:?}",
+                    self.build,
+                    self.build_info_set.info.keys().collect::<Vec<_>>()
+                )
+            })
+    }
+
+    pub fn is_cross_compiling(&self) -> bool {
+        self.host != self.build
+    }
+
+    pub fn tool_path(&mut self, package: &str, tool: &str) -> PathBuf {
+        self.mark_rerun_if_changed();
+        let info = self.build_context_info();
+
+        let path = info.tool_path(package, tool).unwrap_or_else(|| {
+            panic!(
+                "Could not find tool {tool:?} in {package:?} bin paths: {:?}",
+                info.bindir_for(package)
+            )
+        });
+        println!("cargo:rerun-if-changed={}", path.to_string_lossy());
+
+        path
+    }
+
+    pub fn configure(&mut self, package: &str, config: PackageConfig) {
+        self.packages.insert(package.to_string(), config);
+    }
+
+    pub fn set_link_policy(&mut self, package: &str, policy: LinkPolicy) {
+        self.packages.entry(package.to_string()).or_default().link = Some(policy);
+    }
+
+    pub fn set_link_group(&mut self, package: &str, group: bool) {
+        self.packages.entry(package.to_string()).or_default().group = group;
+    }
+
+    pub fn set_link_modifiers(&mut self, package: &str, modifiers: LinkModifiers) {
+        self.packages
+            .entry(package.to_string())
+            .or_default()
+            .modifiers = modifiers;
+    }
+
+    pub fn set_lib_link_modifiers(&mut self, package: &str, lib: &str, modifiers: LinkModifiers) {
+        self.packages
+            .entry(package.to_string())
+            .or_default()
+            .lib_modifiers
+            .insert(lib.to_string(), modifiers);
+    }
+
+    pub fn package_config(&self, package: &str) -> PackageConfig {
+        let mut config = self.packages.get(package).cloned().unwrap_or_default();
+
+        let var = format!(
+            "CONAN_BUILD_{}_LINK",
+            package.to_uppercase().replace(['-', '.'], "_")
+        );
+        println!("cargo:rerun-if-env-changed={var}");
+        if let Ok(policy) = std::env::var(&var) {
+            config.link = Some(
+                policy
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Invalid {var}={policy:?}: {e}")),
+            );
+        }
+
+        config
+    }
+
+    pub fn apply_metadata(&mut self, metadata: &Metadata) {
+        self.mark_rerun_if_changed();
+        if let Some(manifest) = &metadata.manifest {
+            println!("cargo:rerun-if-changed={}", manifest.to_string_lossy());
+        }
+
+        for (package, vars) in &metadata.sys_env {
+            for (name, template) in vars {
+                self.sys_env.register(package, name, template);
+            }
+        }
+        for (package, config) in &metadata.packages {
+            self.configure(package, config.clone());
+            if let Some(version) = &config.version {
+                if self.build_info().try_package(package).is_some()
+                    || metadata.required_packages().contains(&package.as_str())
+                {
+                    self.require(package, version);
+                }
+            }
+        }
+
+        self.depends_on(metadata.required_packages());
+        self.depends_on_optional(metadata.optional.iter().map(String::as_str));
+        if metadata.libcxx {
+            self.depends_on_libcxx_link(metadata.libcxx_link);
+        }
+        if metadata.cfg {
+            let packages = metadata
+                .required_packages()
+                .into_iter()
+                .chain(metadata.optional.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            self.emit_cfg(packages);
+        }
+        if let Some(rust_module) = &metadata.rust_module {
+            self.write_rust_module(rust_module);
+        }
+        if metadata.env_info {
+            self.emit_env_info();
+        }
+        if metadata.rustc_env {
+            let packages = metadata
+                .required_packages()
+                .into_iter()
+                .chain(metadata.optional.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            self.emit_sys_env(packages);
+        }
+    }
+
+    pub fn require(&mut self, package: &str, requirement: &str) -> Version {
+        self.mark_rerun_if_changed();
+        let requirement = requirement
+            .parse::<VersionReq>()
+            .unwrap_or_else(|e| panic!("Invalid requirement for {package:?}: {e}"));
+
+        let info = self.build_info();
+        if info.try_package(package).is_none() {
+            panic!(
+                "Package {package:?} {requirement} is required but not in {:?}",
+                info.path
+            );
+        }
+
+        let version = info.version_for(package);
+        if !requirement.matches(&version) {
+            panic!(
+                "Package {package:?} version {version} does not satisfy {requirement} (from {:?})",
+                info.path
+            );
+        }
+
+        version
+    }
+
+    /// Emits `rustc-cfg` flags for the presence, version and options of each package, along
+    /// with the matching `rustc-check-cfg` declarations.
+    pub fn emit_cfg<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
+        self.mark_rerun_if_changed();
+        let info = self.build_info();
+
+        for package in packages {
+            info.check_cfgs_for(package)
+                .iter()
+                .for_each(Applyable::apply);
+            info.cfgs_for(package).iter().for_each(Applyable::apply);
+        }
+    }
+
     pub fn depends_on<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
         self.mark_rerun_if_changed();
+        let packages = packages.into_iter().collect::<Vec<_>>();
         let info = self.build_info();
-        DependsOn::extend_all(
-            packages
-                .into_iter()
-                .map(|package| info.get_depends_on_package(package)),
-        )
-        .apply()
+        let depends_on =
+            DependsOn::extend_all(packages.iter().map(|package| {
+                info.get_depends_on_configured(package, &self.package_config(package))
+            }));
+        Self::mark_rerun_if_lib_changed(&depends_on);
+        depends_on.apply();
+        self.mark_linked(packages);
+    }
+
+    /// Links the static libraries of all `packages` within a single linker group, for
+    /// circular references that span packages.
+    pub fn depends_on_grouped<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
+        self.mark_rerun_if_changed();
+        let packages = packages.into_iter().collect::<Vec<_>>();
+        let info = self.build_info();
+        let mut depends_on = DependsOn::extend_all(packages.iter().map(|package| {
+            let mut config = self.package_config(package);
+            config.group = true;
+            info.get_depends_on_configured(package, &config)
+        }));
+        depends_on.merge_groups();
+        Self::mark_rerun_if_lib_changed(&depends_on);
+        depends_on.apply();
+        self.mark_linked(packages);
     }
 
     pub fn depends_on_optional<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
         self.mark_rerun_if_changed();
         let info = self.build_info();
-        DependsOn::extend_all(
-            packages
-                .into_iter()
-                .filter(|package| info.try_package(package).is_some())
-                .map(|package| info.get_depends_on_package(package)),
-        )
-        .apply()
+        let packages = packages
+            .into_iter()
+            .filter(|package| info.try_package(package).is_some())
+            .collect::<Vec<_>>();
+        let depends_on =
+            DependsOn::extend_all(packages.iter().map(|package| {
+                info.get_depends_on_configured(package, &self.package_config(package))
+            }));
+        Self::mark_rerun_if_lib_changed(&depends_on);
+        depends_on.apply();
+        self.mark_linked(packages);
     }
 
-    pub fn depends_on_libcxx(&mut self) {
+    fn mark_linked(&mut self, packages: Vec<&str>) {
+        for package in packages {
+            if !self.linked.iter().any(|linked| linked == package) {
+                self.linked.push(package.to_string());
+            }
+        }
+    }
+
+    pub fn linked_packages(&self) -> impl Iterator<Item = &str> {
+        self.linked.iter().map(String::as_str)
+    }
+
+    pub fn link_kind(&self, package: &str) -> Option<Link> {
+        let depends_on = self
+            .build_info()
+            .get_depends_on_configured(package, &self.package_config(package));
+        let is_static = depends_on.libs.iter().map(|lib| lib.is_static);
+        let is_static = is_static.chain(depends_on.groups.iter().map(|_| true));
+
+        match is_static.collect::<Vec<_>>().as_slice() {
+            [] => None,
+            kinds if kinds.iter().all(|&is_static| is_static) => Some(Link::Static),
+            kinds if kinds.iter().all(|&is_static| !is_static) => Some(Link::Shared),
+            _ => None,
+        }
+    }
+
+    /// Writes a Rust module describing the linked packages and settings to `OUT_DIR`, meant
+    /// to be used with `include!(concat!(env!("OUT_DIR"), "/<file_name>"))`.
+    pub fn write_rust_module(&mut self, file_name: &str) -> PathBuf {
+        self.mark_rerun_if_changed();
+        let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR variable must be set");
+        let path = Path::new(&out_dir).join(file_name);
+
+        let file = File::create(&path)
+            .unwrap_or_else(|e| panic!("Failure creating {}: {e}", path.to_string_lossy()));
+        rust_module::write(self, io::BufWriter::new(file))
+            .unwrap_or_else(|e| panic!("Failure writing {}: {e}", path.to_string_lossy()));
+
+        path
+    }
+
+    pub fn sys_env_registry(&mut self) -> &mut SysEnvRegistry {
+        &mut self.sys_env
+    }
+
+    /// Variables that sys crates read to find `package`, with the values for this build.
+    pub fn sys_env(&mut self, package: &str) -> Vec<(String, String)> {
         self.mark_rerun_if_changed();
-        if let Some(cxx) = self.build_info().libcxx() {
-            cxx.apply();
+        let info = self.build_info();
+        if info.try_package(package).is_none() {
+            return Vec::new();
+        }
+
+        // Only tool variables need the build context, which is missing when cross compiling
+        // with just the host build info
+        let link = self.link_kind(package);
+        let build = self.build_info_set.get_current_target(&self.build);
+        self.sys_env.resolve(info, build, package, link)
+    }
+
+    /// Forwards the sys crate variables of `packages` to the crate with `rustc-env`.
+    pub fn emit_sys_env<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
+        for package in packages {
+            for (name, value) in self.sys_env(package) {
+                println!("cargo:rustc-env={name}={value}");
+            }
+        }
+    }
+
+    /// Sets the sys crate variables of `packages` in the build script's own environment,
+    /// for tools such as prost-build or bindgen run later. Variables already set are kept.
+    pub fn export_sys_env<'a, I: IntoIterator<Item = &'a str>>(&mut self, packages: I) {
+        for package in packages {
+            for (name, value) in self.sys_env(package) {
+                println!("cargo:rerun-if-env-changed={name}");
+                if std::env::var_os(&name).is_none() {
+                    std::env::set_var(name, value);
+                }
+            }
         }
     }
 
-    pub fn generate_env_source(&self) {
-        let mut sh = File::create("env.sh").unwrap();
-        let mut ps1 = File::create("env.ps1").unwrap();
+    /// Forwards the variables defined by the packages' env, runenv and user info to the
+    /// crate with `rustc-env`. Path lists such as `PATH` are left to the env scripts.
+    pub fn emit_env_info(&mut self) {
+        self.mark_rerun_if_changed();
+        let info = self.build_info();
 
-        for (is_host, info) in self.build_info_set.all_targets(&self.host) {
-            info.write_env_source(is_host, &mut sh, &mut ps1).unwrap();
+        for env in [
+            info.env_info(),
+            info.buildenv_info(),
+            info.runenv_info(),
+            &info.user_info_env(),
+        ] {
+            for var in env {
+                if let EnvOp::Set(value) = &var.op {
+                    println!("cargo:rustc-env={}={value}", var.name);
+                }
+            }
         }
     }
 
+    pub fn depends_on_libcxx(&mut self) {
+        self.depends_on_libcxx_link(None);
+    }
+
+    pub fn depends_on_libcxx_link(&mut self, link: Option<Link>) {
+        self.mark_rerun_if_changed();
+
+        let var = "CONAN_BUILD_LIBCXX_LINK";
+        println!("cargo:rerun-if-env-changed={var}");
+        let link = match std::env::var(var) {
+            Ok(link) => Some(match link.as_str() {
+                "static" => Link::Static,
+                "shared" => Link::Shared,
+                link => panic!("Invalid {var}={link:?}, expected static or shared"),
+            }),
+            Err(_) => link,
+        };
+
+        self.build_info()
+            .libcxx_libs(link)
+            .iter()
+            .for_each(Applyable::apply);
+    }
+
+    pub fn env(&self) -> io::Result<EnvSet> {
+        EnvSet::from_build_info_set(&self.build_info_set, &self.host, &self.build, &self.sys_env)
+    }
+
+    /// Merges `[env]` and `[target]` entries for all build infos into the cargo config at
+    /// `path`, usually `.cargo/config.toml`.
+    pub fn write_cargo_config<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut targets = self
+            .build_info_set
+            .all_targets(&self.host)
+            .map(|(_, info)| (info.target(), info.target_config(&self.host)))
+            .collect::<Vec<_>>();
+        targets.sort_by_key(|(target, _)| *target);
+
+        cargo_config::write(path.as_ref(), &self.env()?, &targets)
+    }
+
+    pub fn generate_env_source(&self, formats: &[&dyn ShellFormat]) -> io::Result<()> {
+        let env = self.env()?;
+
+        for format in formats {
+            write_replacing(&format.file_name(), |w| {
+                format.header(w)?;
+                env.write(*format, w)
+            })?;
+
+            if let Some(file_name) = format.deactivate_file_name() {
+                write_replacing(&file_name, |w| {
+                    format.header(w)?;
+                    env.write_deactivate(*format, w)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn option(&self, package: &str, option: &str) -> Option<&OptionValue> {
+        self.build_info().option(package, option)
+    }
+
+    pub fn is_package_shared(&self, package: &str) -> Option<bool> {
+        self.build_info().is_package_shared(package)
+    }
+
     pub fn package_is_shared(options: &HashMap<String, String>, package: &str) -> Option<bool> {
         let option = format!("{package}:shared");
 
@@ -417,6 +1314,294 @@ pub enum Link {
     Shared,
 }
 
+/// C++ standard library selected by the `compiler.libcxx` setting.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LibCxx {
+    LibStdCxx,
+    LibStdCxx11,
+    LibCxx,
+    CxxShared,
+    CxxStatic,
+}
+impl LibCxx {
+    pub fn default_link(self) -> Link {
+        match self {
+            LibCxx::CxxStatic => Link::Static,
+            _ => Link::Shared,
+        }
+    }
+
+    /// Libraries to link. Static runtimes are linked without bundling, leaving the final
+    /// link to the C++ aware linker driver, the equivalent of `-static-libstdc++`.
+    pub fn libs(self, link: Link) -> Vec<Lib> {
+        let names: &[&str] = match (self, link) {
+            (LibCxx::LibStdCxx | LibCxx::LibStdCxx11, _) => &["stdc++"],
+            (LibCxx::LibCxx, Link::Shared) => &["c++"],
+            (LibCxx::LibCxx, Link::Static) => &["c++", "c++abi"],
+            (LibCxx::CxxShared | LibCxx::CxxStatic, Link::Shared) => &["c++_shared"],
+            (LibCxx::CxxShared | LibCxx::CxxStatic, Link::Static) => &["c++_static", "c++abi"],
+        };
+        let modifiers = match link {
+            Link::Static => LinkModifiers {
+                bundle: Some(false),
+                ..LinkModifiers::default()
+            },
+            Link::Shared => LinkModifiers::default(),
+        };
+
+        names
+            .iter()
+            .map(|name| Lib {
+                is_static: link == Link::Static,
+                name: name.to_string(),
+                modifiers,
+            })
+            .collect()
+    }
+}
+impl FromStr for LibCxx {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "libstdc++" => LibCxx::LibStdCxx,
+            "libstdc++11" => LibCxx::LibStdCxx11,
+            "libc++" => LibCxx::LibCxx,
+            "c++_shared" => LibCxx::CxxShared,
+            "c++_static" => LibCxx::CxxStatic,
+            s => return Err(format!("unsupported C++ standard library {s:?}")),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LinkPolicy {
+    PreferStatic,
+    PreferShared,
+    RequireStatic,
+    RequireShared,
+    FromOptions,
+}
+impl FromStr for LinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "static" | "require-static" => LinkPolicy::RequireStatic,
+            "shared" | "require-shared" => LinkPolicy::RequireShared,
+            "prefer-static" => LinkPolicy::PreferStatic,
+            "prefer-shared" => LinkPolicy::PreferShared,
+            "from-options" => LinkPolicy::FromOptions,
+            s => {
+                return Err(format!(
+                    "unknown link policy {s:?}, expected static, shared, prefer-static, prefer-shared or from-options"
+                ))
+            }
+        })
+    }
+}
+
+/// Value of a Conan package option, as found in the `options` section of the build info.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OptionValue {
+    Bool(bool),
+    None,
+    Value(String),
+}
+impl OptionValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            OptionValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OptionValue::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == OptionValue::None
+    }
+}
+impl From<&Value> for OptionValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Bool(value) => OptionValue::Bool(*value),
+            Value::Null => OptionValue::None,
+            Value::String(value) => value.parse().unwrap(),
+            value => OptionValue::Value(value.to_string()),
+        }
+    }
+}
+impl FromStr for OptionValue {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            s if s.eq_ignore_ascii_case("true") => OptionValue::Bool(true),
+            s if s.eq_ignore_ascii_case("false") => OptionValue::Bool(false),
+            "None" => OptionValue::None,
+            s => OptionValue::Value(s.to_string()),
+        })
+    }
+}
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Bool(true) => f.write_str("True"),
+            OptionValue::Bool(false) => f.write_str("False"),
+            OptionValue::None => f.write_str("None"),
+            OptionValue::Value(value) => f.write_str(value),
+        }
+    }
+}
+
+/// Reports a problem with `cargo:warning` when running in a build script, since cargo hides
+/// the stderr of successful build scripts.
+fn warn(message: &str) {
+    match std::env::var_os("OUT_DIR") {
+        Some(_) => println!("cargo:warning={}", message.replace('\n', " ")),
+        None => eprintln!("Warning: {message}"),
+    }
+}
+
+/// Writes `path` through a temporary file, so that a failure leaves any previous file intact.
+fn write_replacing<F>(path: &str, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn io::Write) -> io::Result<()>,
+{
+    let tmp = format!("{path}.tmp");
+    let result = File::create(&tmp).and_then(|mut file| {
+        write(&mut file)?;
+        file.flush()
+    });
+    match result {
+        Ok(()) => std::fs::rename(&tmp, path),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Accepts both `{"pkg": {"option": value}}` and the flat `{"pkg:option": value}` layouts.
+fn package_options(root: &Value) -> HashMap<String, HashMap<String, OptionValue>> {
+    let mut result = HashMap::<String, HashMap<String, OptionValue>>::new();
+
+    for (key, value) in root.as_object().into_iter().flatten() {
+        match (value.as_object(), key.split_once(':')) {
+            (Some(options), _) => {
+                let options = options
+                    .iter()
+                    .map(|(option, value)| (option.clone(), OptionValue::from(value)));
+                result.entry(key.clone()).or_default().extend(options);
+            }
+            (None, Some((package, option))) => {
+                result
+                    .entry(package.to_string())
+                    .or_default()
+                    .insert(option.to_string(), OptionValue::from(value));
+            }
+            (None, None) => {}
+        }
+    }
+
+    result
+}
+
+/// Environment published by the packages, from the aggregated `top_key` when present or
+/// else from `dep_key` of each dependency. Strings define a variable and lists prepend to
+/// it, as in Conan 1. Objects such as `{"append": [...]}` give the action explicitly.
+fn env_info(root: &Value, top_key: &str, dep_key: &str) -> EnvSet {
+    let mut env = EnvSet::new();
+    let sources = match root.get(top_key) {
+        Some(top) => vec![top],
+        None => root["dependencies"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|dep| dep.get(dep_key))
+            .collect(),
+    };
+
+    for (name, value) in sources.into_iter().flat_map(|source| {
+        source
+            .as_object()
+            .unwrap_or_else(|| panic!("{top_key} must be an object"))
+    }) {
+        let (action, value) = match value {
+            Value::Object(object) if object.len() == 1 => {
+                let (action, value) = object.iter().next().unwrap();
+                (action.as_str(), value)
+            }
+            Value::Array(_) => ("prepend", value),
+            value => ("define", value),
+        };
+        let entries = match value {
+            Value::Array(entries) => entries.iter().map(json_string).collect(),
+            value => vec![json_string(value)],
+        };
+
+        match action {
+            "prepend" => env.prepend(name.as_str(), entries),
+            "append" => env.append(name.as_str(), entries),
+            "define" => env.set(name.as_str(), entries.join(" ")),
+            action => warn(&format!("ignoring {action:?} of {name} in {top_key}")),
+        }
+    }
+
+    env
+}
+
+fn user_info(root: &Value) -> HashMap<String, HashMap<String, String>> {
+    let by_package = match root.get("deps_user_info") {
+        Some(top) => top
+            .as_object()
+            .expect("deps_user_info must be an object")
+            .iter()
+            .map(|(package, values)| (package.as_str(), values))
+            .collect::<Vec<_>>(),
+        None => root["dependencies"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|dep| Some((dep["name"].as_str()?, dep.get("user_info")?)))
+            .collect(),
+    };
+
+    by_package
+        .into_iter()
+        .map(|(package, values)| {
+            let values = values
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(key, value)| (key.clone(), json_string(value)))
+                .collect();
+            (package.to_string(), values)
+        })
+        .collect()
+}
+
+fn json_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn push_unique<'a, I: IntoIterator<Item = &'a str>>(dirs: &mut Vec<&'a str>, new: I) {
+    for dir in new {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+}
+
 fn build_info(root: &Value) -> HashMap<String, Value> {
     root["dependencies"]
         .as_array()
@@ -429,12 +1614,21 @@ fn build_info(root: &Value) -> HashMap<String, Value> {
         .collect()
 }
 
-fn find_all_libs<'a, I>(it: I) -> io::Result<HashMap<String, Link>>
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct LibFile {
+    pub link: Link,
+    pub path: PathBuf,
+}
+
+fn find_all_libs<'a, I>(it: I) -> io::Result<HashMap<String, HashMap<String, Vec<LibFile>>>>
 where
     I: Iterator<Item = (&'a String, &'a Value)>,
 {
     let mut result = HashMap::new();
-    for (_, v) in it {
+    for (package, v) in it {
+        let package_libs: &mut HashMap<String, Vec<LibFile>> =
+            result.entry(package.clone()).or_default();
+
         for path in BuildInfo::libdir_for_package(v) {
             let libs = Path::new(path)
                 .read_dir()
@@ -462,11 +1656,11 @@ where
                             false => Link::Static,
                         };
 
-                        return Some(Ok((lib.to_string(), link)));
+                        return Some(Ok((lib.to_string(), link, entry.path())));
                     }
 
                     let link;
-                    if lib.ends_with(".so") {
+                    if lib.ends_with(".so") || lib.ends_with(".dylib") {
                         link = Link::Shared;
                     } else if lib.ends_with(".a") {
                         link = Link::Static;
@@ -484,14 +1678,21 @@ where
                         None => lib,
                     };
 
-                    Some(Ok((lib.to_string(), link)))
+                    Some(Ok((lib.to_string(), link, entry.path())))
                 });
 
             for lib_r in libs {
-                let (key, lib) = lib_r?;
-                result.insert(key, lib);
+                let (key, link, path) = lib_r?;
+                package_libs
+                    .entry(key)
+                    .or_default()
+                    .push(LibFile { link, path });
             }
         }
+
+        for files in package_libs.values_mut() {
+            files.sort();
+        }
     }
 
     Ok(result)
@@ -501,21 +1702,152 @@ pub trait Applyable {
     fn apply(&self);
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct LinkModifiers {
+    pub whole_archive: Option<bool>,
+    pub bundle: Option<bool>,
+    pub verbatim: Option<bool>,
+}
+impl LinkModifiers {
+    pub fn is_empty(&self) -> bool {
+        *self == LinkModifiers::default()
+    }
+
+    pub fn or(self, fallback: LinkModifiers) -> LinkModifiers {
+        LinkModifiers {
+            whole_archive: self.whole_archive.or(fallback.whole_archive),
+            bundle: self.bundle.or(fallback.bundle),
+            verbatim: self.verbatim.or(fallback.verbatim),
+        }
+    }
+
+    fn to_string_for(self, is_static: bool) -> String {
+        let mut modifiers = Vec::new();
+        let mut push = |name: &str, value: Option<bool>| {
+            if let Some(value) = value {
+                let sign = match value {
+                    true => '+',
+                    false => '-',
+                };
+                modifiers.push(format!("{sign}{name}"));
+            }
+        };
+
+        if is_static {
+            push("whole-archive", self.whole_archive);
+            push("bundle", self.bundle);
+        }
+        push("verbatim", self.verbatim);
+
+        modifiers.join(",")
+    }
+}
+impl fmt::Display for LinkModifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_for(true))
+    }
+}
+impl FromStr for LinkModifiers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = LinkModifiers::default();
+        for modifier in s.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            let (value, name) = if let Some(name) = modifier.strip_prefix('+') {
+                (true, name)
+            } else if let Some(name) = modifier.strip_prefix('-') {
+                (false, name)
+            } else {
+                return Err(format!("link modifier {modifier:?} must start with + or -"));
+            };
+
+            let field = match name {
+                "whole-archive" => &mut modifiers.whole_archive,
+                "bundle" => &mut modifiers.bundle,
+                "verbatim" => &mut modifiers.verbatim,
+                name => {
+                    return Err(format!(
+                        "unknown link modifier {name:?}, expected whole-archive, bundle or verbatim"
+                    ))
+                }
+            };
+            *field = Some(value);
+        }
+
+        Ok(modifiers)
+    }
+}
+
 pub struct Lib {
     pub is_static: bool,
     pub name: String,
+    pub modifiers: LinkModifiers,
 }
 impl Applyable for Lib {
     fn apply(&self) {
         let name = &self.name;
         let is_static = self.is_static;
+        let modifiers = self.modifiers.to_string_for(is_static);
 
-        let static_ = match is_static {
-            true => "static=",
-            false => "",
+        let kind = match (is_static, modifiers.is_empty()) {
+            (true, true) => "static=".to_string(),
+            (false, true) => "".to_string(),
+            (true, false) => format!("static:{modifiers}="),
+            (false, false) => format!("dylib:{modifiers}="),
         };
 
-        println!("cargo:rustc-link-lib={static_}{name}");
+        println!("cargo:rustc-link-lib={kind}{name}");
+    }
+}
+
+pub struct Cfg {
+    pub name: String,
+    pub value: Option<String>,
+}
+impl Cfg {
+    pub fn ident(name: &str) -> String {
+        name.chars()
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c.to_ascii_lowercase(),
+                false => '_',
+            })
+            .collect()
+    }
+}
+impl Applyable for Cfg {
+    fn apply(&self) {
+        match &self.value {
+            Some(value) => println!("cargo:rustc-cfg={name}={value:?}", name = self.name),
+            None => println!("cargo:rustc-cfg={name}", name = self.name),
+        }
+    }
+}
+
+pub struct CheckCfg {
+    pub name: String,
+    pub any_value: bool,
+}
+impl CheckCfg {
+    pub fn name(name: &str) -> CheckCfg {
+        CheckCfg {
+            name: name.to_string(),
+            any_value: false,
+        }
+    }
+
+    pub fn values(name: &str) -> CheckCfg {
+        CheckCfg {
+            name: name.to_string(),
+            any_value: true,
+        }
+    }
+}
+impl Applyable for CheckCfg {
+    fn apply(&self) {
+        match self.any_value {
+            true => println!("cargo:rustc-check-cfg=cfg({}, values(any()))", self.name),
+            false => println!("cargo:rustc-check-cfg=cfg({})", self.name),
+        }
     }
 }
 
@@ -526,15 +1858,46 @@ impl Applyable for LibDir {
     }
 }
 
+/// Static archives linked between `--start-group` and `--end-group`, so that the linker
+/// resolves circular references among them. Only meaningful for GNU-style ELF linkers, and
+/// being a `rustc-link-arg` it only applies to the targets linked by the current package,
+/// dependents link the same archives through the regular `rustc-link-lib` lines.
+pub struct LinkGroup(pub Vec<PathBuf>);
+impl Applyable for LinkGroup {
+    fn apply(&self) {
+        println!("cargo:rustc-link-arg=-Wl,--start-group");
+        for archive in &self.0 {
+            println!("cargo:rustc-link-arg={}", archive.to_string_lossy());
+        }
+        println!("cargo:rustc-link-arg=-Wl,--end-group");
+    }
+}
+
 #[derive(Default)]
 pub struct DependsOn {
     pub libs: Vec<Lib>,
+    pub groups: Vec<LinkGroup>,
     pub libdirs: Vec<LibDir>,
+    pub files: Vec<PathBuf>,
 }
 impl DependsOn {
     pub fn extend(&mut self, rhs: DependsOn) {
         self.libs.extend(rhs.libs);
+        self.groups.extend(rhs.groups);
         self.libdirs.extend(rhs.libdirs);
+        self.files.extend(rhs.files);
+    }
+
+    pub fn merge_groups(&mut self) {
+        let archives = self
+            .groups
+            .drain(..)
+            .flat_map(|group| group.0)
+            .collect::<Vec<_>>();
+
+        if !archives.is_empty() {
+            self.groups.push(LinkGroup(archives));
+        }
     }
 
     fn extend_all<I: IntoIterator<Item = DependsOn>>(iter: I) -> DependsOn {
@@ -549,6 +1912,205 @@ impl DependsOn {
 impl Applyable for DependsOn {
     fn apply(&self) {
         self.libs.iter().for_each(Applyable::apply);
+        self.groups.iter().for_each(Applyable::apply);
         self.libdirs.iter().for_each(Applyable::apply);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Build info of `packages`, each with a lib dir holding the given files.
+    fn with_libs(dir: &TempDir, options: &str, packages: &[(&str, &[&str])]) -> BuildInfo {
+        let mut dependencies = Vec::new();
+        for (package, files) in packages {
+            let lib_dir = dir.path().join(package).join("lib");
+            for file in *files {
+                dir.write(&format!("{package}/lib/{file}"), "");
+            }
+            dependencies.push(format!(
+                r#"{{"name": "{package}", "version": "1.0", "rootpath": "/{package}",
+                "lib_paths": [{lib_dir:?}], "libs": ["foo"], "include_paths": [],
+                "bin_paths": []}}"#
+            ));
+        }
+        let path = dir.write(
+            "conanbuildinfo.json",
+            &format!(
+                r#"{{"settings": {{"os": "Linux", "arch": "x86_64"}}, "options": {options},
+                "dependencies": [{}]}}"#,
+                dependencies.join(", ")
+            ),
+        );
+
+        BuildInfo::read_build_info(path).unwrap()
+    }
+
+    #[test]
+    fn links_the_variant_of_the_shared_option() {
+        let dir = TempDir::new("link-option");
+        for (shared, link, file) in [
+            ("True", Link::Shared, "libfoo.so"),
+            ("False", Link::Static, "libfoo.a"),
+        ] {
+            let options = format!(r#"{{"foo": {{"shared": "{shared}"}}}}"#);
+            let info = with_libs(&dir, &options, &[("foo", &["libfoo.a", "libfoo.so"])]);
+
+            assert_eq!(
+                info.lib_variants("foo", "foo"),
+                [Link::Static, Link::Shared]
+            );
+            assert_eq!(info.default_link("foo", "foo"), link);
+            assert_eq!(info.resolve_link("foo", "foo", None), link);
+            let lib_file = info.resolve_lib_file("foo", "foo", None).unwrap();
+            assert!(lib_file.path.ends_with(file), "{lib_file:?}");
+        }
+    }
+
+    #[test]
+    fn prefers_static_without_shared_option() {
+        let dir = TempDir::new("link-static");
+        let info = with_libs(&dir, "{}", &[("foo", &["libfoo.a", "libfoo.so"])]);
+
+        assert_eq!(info.default_link("foo", "foo"), Link::Static);
+        assert_eq!(info.resolve_link("foo", "foo", None), Link::Static);
+        assert_eq!(info.default_package_link("foo"), Some(Link::Static));
+    }
+
+    #[test]
+    fn applies_link_policies() {
+        let dir = TempDir::new("link-policy");
+        let options = r#"{"foo": {"shared": "True"}}"#;
+        let info = with_libs(&dir, options, &[("foo", &["libfoo.a", "libfoo.so"])]);
+
+        for (policy, link) in [
+            (LinkPolicy::PreferStatic, Link::Static),
+            (LinkPolicy::PreferShared, Link::Shared),
+            (LinkPolicy::RequireStatic, Link::Static),
+            (LinkPolicy::RequireShared, Link::Shared),
+            (LinkPolicy::FromOptions, Link::Shared),
+        ] {
+            assert_eq!(
+                info.resolve_link("foo", "foo", Some(policy)),
+                link,
+                "{policy:?}"
+            );
+        }
+
+        let dir = TempDir::new("link-policy-static");
+        let info = with_libs(&dir, "{}", &[("foo", &["libfoo.a"])]);
+        assert_eq!(
+            info.resolve_link("foo", "foo", Some(LinkPolicy::PreferShared)),
+            Link::Static
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Shared library \"foo\" required by RequireShared")]
+    fn require_shared_panics_without_shared_library() {
+        let dir = TempDir::new("link-require-shared");
+        let info = with_libs(&dir, "{}", &[("foo", &["libfoo.a"])]);
+
+        info.resolve_link("foo", "foo", Some(LinkPolicy::RequireShared));
+    }
+
+    #[test]
+    fn resolves_libraries_per_package() {
+        let dir = TempDir::new("link-per-package");
+        let info = with_libs(&dir, "{}", &[("a", &["libfoo.a"]), ("b", &["libfoo.so"])]);
+
+        assert_eq!(info.lib_providers("foo"), ["a", "b"]);
+        assert_eq!(info.default_link("a", "foo"), Link::Static);
+        assert_eq!(info.default_link("b", "foo"), Link::Shared);
+        assert!(info
+            .resolve_lib_file("b", "foo", None)
+            .unwrap()
+            .path
+            .starts_with(dir.path().join("b")));
+    }
+
+    fn check_cfg_names(info: &BuildInfo, package: &str) -> Vec<String> {
+        info.check_cfgs_for(package)
+            .into_iter()
+            .map(|check_cfg| check_cfg.name)
+            .collect()
+    }
+
+    #[test]
+    fn declares_check_cfgs_for_every_option() {
+        let dir = TempDir::new("check-cfgs");
+        let path = dir.write(
+            "conanbuildinfo.json",
+            r#"{"settings": {"os": "Linux", "arch": "x86_64"},
+            "options": {"zeromq": {"shared": "False", "encryption": "None"},
+                        "libsodium": {"shared": "True"}},
+            "dependencies": [{"name": "zeromq", "version": "4.3.4", "rootpath": "/zmq",
+            "lib_paths": [], "libs": [], "include_paths": [], "bin_paths": []}]}"#,
+        );
+        let info = BuildInfo::read_build_info(path).unwrap();
+
+        let emitted = info
+            .cfgs_for("zeromq")
+            .into_iter()
+            .map(|cfg| cfg.name)
+            .collect::<Vec<_>>();
+        assert_eq!(emitted, ["conan_zeromq", "conan_zeromq_version"]);
+        assert_eq!(
+            check_cfg_names(&info, "zeromq"),
+            [
+                "conan_zeromq",
+                "conan_zeromq_version",
+                "conan_zeromq_option_encryption",
+                "conan_zeromq_option_shared",
+            ]
+        );
+        assert!(info.cfgs_for("libsodium").is_empty());
+        assert_eq!(
+            check_cfg_names(&info, "libsodium"),
+            [
+                "conan_libsodium",
+                "conan_libsodium_version",
+                "conan_libsodium_option_shared",
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    /// Directory for the files of a test, removed when dropped.
+    pub(crate) struct TempDir(PathBuf);
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir()
+                .join(format!("conan-build-test-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        pub(crate) fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Writes `contents` to `path` below the directory, creating the parents.
+        pub(crate) fn write(&self, path: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+}
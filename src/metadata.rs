@@ -0,0 +1,270 @@
+use crate::{Link, LinkModifiers, LinkPolicy};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+#[derive(Debug, Default, Clone)]
+pub struct PackageConfig {
+    pub components: Option<Vec<String>>,
+    pub version: Option<String>,
+    pub link: Option<LinkPolicy>,
+    pub group: bool,
+    pub modifiers: LinkModifiers,
+    pub lib_modifiers: BTreeMap<String, LinkModifiers>,
+}
+impl PackageConfig {
+    pub fn modifiers_for(&self, lib: &str) -> LinkModifiers {
+        self.lib_modifiers
+            .get(lib)
+            .copied()
+            .unwrap_or_default()
+            .or(self.modifiers)
+    }
+
+    fn from_toml(package: &str, table: &Table) -> PackageConfig {
+        let components = table
+            .get("components")
+            .map(|components| string_list(components, &format!("packages.{package}.components")));
+        let link = table.get("link").map(|link| {
+            link.as_str()
+                .unwrap_or_else(|| panic!("packages.{package}.link must be a string"))
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid packages.{package}.link: {e}"))
+        });
+
+        let version = table.get("version").map(|version| {
+            version
+                .as_str()
+                .unwrap_or_else(|| panic!("packages.{package}.version must be a string"))
+                .to_string()
+        });
+        let group = table.get("group").is_some_and(|group| {
+            group
+                .as_bool()
+                .unwrap_or_else(|| panic!("packages.{package}.group must be a boolean"))
+        });
+        let modifiers = table
+            .get("modifiers")
+            .map(|modifiers| parse_modifiers(modifiers, &format!("packages.{package}.modifiers")))
+            .unwrap_or_default();
+        let lib_modifiers = table
+            .get("libs")
+            .map(|libs| {
+                libs.as_table()
+                    .unwrap_or_else(|| panic!("packages.{package}.libs must be a table"))
+                    .iter()
+                    .map(|(lib, config)| {
+                        let key = format!("packages.{package}.libs.{lib}.modifiers");
+                        let modifiers = config
+                            .get("modifiers")
+                            .map(|modifiers| parse_modifiers(modifiers, &key))
+                            .unwrap_or_default();
+                        (lib.clone(), modifiers)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        PackageConfig {
+            components,
+            version,
+            link,
+            group,
+            modifiers,
+            lib_modifiers,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Metadata {
+    pub manifest: Option<PathBuf>,
+    pub requires: Vec<String>,
+    pub optional: Vec<String>,
+    pub libcxx: bool,
+    pub libcxx_link: Option<Link>,
+    pub cfg: bool,
+    pub rust_module: Option<String>,
+    pub rustc_env: bool,
+    pub env_info: bool,
+    pub sys_env: BTreeMap<String, BTreeMap<String, String>>,
+    pub packages: BTreeMap<String, PackageConfig>,
+    pub features: BTreeMap<String, Vec<String>>,
+}
+impl Metadata {
+    pub fn from_env() -> io::Result<Metadata> {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR must be set");
+        Self::from_manifest(Path::new(&dir).join("Cargo.toml"))
+    }
+
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
+        let manifest = std::fs::read_to_string(path.as_ref())?
+            .parse::<Table>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut metadata = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("conan"))
+            .map(|conan| {
+                Self::from_toml(
+                    conan
+                        .as_table()
+                        .expect("package.metadata.conan must be a table"),
+                )
+            })
+            .unwrap_or_default();
+        metadata.manifest = Some(path.as_ref().to_owned());
+
+        Ok(metadata)
+    }
+
+    pub fn from_toml(conan: &Table) -> Metadata {
+        let list = |key: &str| {
+            conan
+                .get(key)
+                .map(|value| string_list(value, key))
+                .unwrap_or_default()
+        };
+
+        let (libcxx, libcxx_link) = match conan.get("libcxx") {
+            None => (false, None),
+            Some(Value::Boolean(libcxx)) => (*libcxx, None),
+            Some(Value::String(link)) if link == "static" => (true, Some(Link::Static)),
+            Some(Value::String(link)) if link == "shared" => (true, Some(Link::Shared)),
+            Some(_) => panic!("libcxx must be a boolean, \"static\" or \"shared\""),
+        };
+        let cfg = conan
+            .get("cfg")
+            .is_some_and(|cfg| cfg.as_bool().expect("cfg must be a boolean"));
+        let rust_module = conan.get("rust_module").map(|rust_module| {
+            rust_module
+                .as_str()
+                .expect("rust_module must be a string")
+                .to_string()
+        });
+        let rustc_env = conan
+            .get("rustc_env")
+            .is_some_and(|rustc_env| rustc_env.as_bool().expect("rustc_env must be a boolean"));
+        let env_info = conan
+            .get("env_info")
+            .is_some_and(|env_info| env_info.as_bool().expect("env_info must be a boolean"));
+        let sys_env = conan
+            .get("sys_env")
+            .map(|sys_env| {
+                sys_env
+                    .as_table()
+                    .expect("sys_env must be a table")
+                    .iter()
+                    .map(|(package, vars)| {
+                        let vars = vars
+                            .as_table()
+                            .unwrap_or_else(|| panic!("sys_env.{package} must be a table"))
+                            .iter()
+                            .map(|(name, template)| {
+                                let template = template.as_str().unwrap_or_else(|| {
+                                    panic!("sys_env.{package}.{name} must be a string")
+                                });
+                                (name.clone(), template.to_string())
+                            })
+                            .collect();
+                        (package.clone(), vars)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let packages = conan
+            .get("packages")
+            .map(|packages| {
+                packages
+                    .as_table()
+                    .expect("packages must be a table")
+                    .iter()
+                    .map(|(package, config)| {
+                        let config = config
+                            .as_table()
+                            .unwrap_or_else(|| panic!("packages.{package} must be a table"));
+                        (package.clone(), PackageConfig::from_toml(package, config))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let features = conan
+            .get("features")
+            .map(|features| {
+                features
+                    .as_table()
+                    .expect("features must be a table")
+                    .iter()
+                    .map(|(feature, packages)| {
+                        let packages = string_list(packages, &format!("features.{feature}"));
+                        (feature.clone(), packages)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Metadata {
+            manifest: None,
+            requires: list("requires"),
+            optional: list("optional"),
+            libcxx,
+            libcxx_link,
+            cfg,
+            rust_module,
+            rustc_env,
+            env_info,
+            sys_env,
+            packages,
+            features,
+        }
+    }
+
+    pub fn enabled_features(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.features
+            .iter()
+            .filter(|(feature, _)| {
+                let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+                std::env::var_os(var).is_some()
+            })
+            .map(|(feature, packages)| (feature.as_str(), packages.as_slice()))
+    }
+
+    pub fn required_packages(&self) -> Vec<&str> {
+        let mut packages = Vec::new();
+        let enabled = self
+            .enabled_features()
+            .flat_map(|(_, packages)| packages.iter());
+        for package in self.requires.iter().chain(enabled) {
+            if !packages.contains(&package.as_str()) {
+                packages.push(package.as_str());
+            }
+        }
+
+        packages
+    }
+}
+
+fn parse_modifiers(value: &Value, key: &str) -> LinkModifiers {
+    value
+        .as_str()
+        .unwrap_or_else(|| panic!("{key} must be a string"))
+        .parse()
+        .unwrap_or_else(|e| panic!("Invalid {key}: {e}"))
+}
+
+fn string_list(value: &Value, key: &str) -> Vec<String> {
+    value
+        .as_array()
+        .unwrap_or_else(|| panic!("{key} must be an array of strings"))
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .unwrap_or_else(|| panic!("{key} must be an array of strings"))
+                .to_string()
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+use crate::{Cfg, Conan, Link};
+use std::io;
+
+pub(crate) fn write<W: io::Write>(conan: &Conan, mut w: W) -> io::Result<()> {
+    let info = conan.build_info();
+
+    writeln!(w, "// Generated by conan-build from {:?}", info.path)?;
+    writeln!(w)?;
+    writeln!(w, "#[allow(dead_code)]")?;
+    writeln!(w, "#[derive(Debug, Clone, Copy)]")?;
+    writeln!(w, "pub struct ConanPackage {{")?;
+    writeln!(w, "    pub name: &'static str,")?;
+    writeln!(w, "    pub version: &'static str,")?;
+    writeln!(w, "    pub license: &'static [&'static str],")?;
+    writeln!(w, "    pub link: Option<&'static str>,")?;
+    writeln!(
+        w,
+        "    pub options: &'static [(&'static str, &'static str)],"
+    )?;
+    writeln!(w, "    pub include_dirs: &'static [&'static str],")?;
+    writeln!(w, "}}")?;
+    writeln!(w)?;
+
+    for (name, setting) in [
+        ("OS", "os"),
+        ("ARCH", "arch"),
+        ("BUILD_TYPE", "build_type"),
+        ("COMPILER", "compiler"),
+        ("COMPILER_VERSION", "compiler.version"),
+        ("LIBCXX", "compiler.libcxx"),
+    ] {
+        writeln!(w, "#[allow(dead_code)]")?;
+        writeln!(
+            w,
+            "pub const {name}: Option<&str> = {};",
+            option(info.setting(setting))
+        )?;
+    }
+    writeln!(w)?;
+
+    let mut consts = Vec::new();
+    for package in conan.linked_packages() {
+        let link = conan.link_kind(package).map(|link| match link {
+            Link::Static => "static",
+            Link::Shared => "shared",
+        });
+        let mut options = info
+            .options_for(package)
+            .into_iter()
+            .flatten()
+            .map(|(option, value)| (option.as_str(), value.to_string()))
+            .collect::<Vec<_>>();
+        options.sort();
+
+        // Prefixed so that names starting with a digit stay valid and don't clash with the settings
+        let name = format!("PKG_{}", Cfg::ident(package).to_uppercase());
+        if let Some((other, _)) = consts.iter().find(|(_, existing)| *existing == name) {
+            panic!("Conan packages {other} and {package} both map to the constant {name}");
+        }
+        writeln!(w, "#[allow(dead_code)]")?;
+        writeln!(w, "pub const {name}: ConanPackage = ConanPackage {{")?;
+        writeln!(w, "    name: {package:?},")?;
+        writeln!(w, "    version: {:?},", info.version_for(package).as_str())?;
+        writeln!(w, "    license: &{:?},", info.license_for(package))?;
+        writeln!(w, "    link: {},", option(link))?;
+        writeln!(w, "    options: &{options:?},")?;
+        writeln!(w, "    include_dirs: &{:?},", info.includes_for(package))?;
+        writeln!(w, "}};")?;
+        consts.push((package, name));
+    }
+
+    writeln!(w)?;
+    writeln!(w, "#[allow(dead_code)]")?;
+    writeln!(
+        w,
+        "pub const PACKAGES: &[ConanPackage] = &[{}];",
+        consts
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+
+    w.flush()
+}
+
+fn option(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("Some({value:?})"),
+        None => "None".to_string(),
+    }
+}
@@ -0,0 +1,822 @@
+use std::{borrow::Cow, io};
+
+pub trait ShellFormat {
+    fn name(&self) -> &'static str;
+
+    fn extension(&self) -> &'static str;
+
+    fn path_separator(&self) -> char;
+
+    /// Spells an entry of a path list for this format. Windows paths are converted to the
+    /// `/c/dir` form of MSYS2 and Git Bash when `:` separates the entries.
+    fn path_entry<'a>(&self, entry: &'a str) -> Cow<'a, str> {
+        match self.path_separator() {
+            ':' => posix_path(entry),
+            _ => Cow::Borrowed(entry),
+        }
+    }
+
+    fn header(&self, _w: &mut dyn io::Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Declares a file whose changes should reload the environment, for formats that can.
+    fn watch(&self, _w: &mut dyn io::Write, _path: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()>;
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()>;
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()>;
+
+    /// Saves the current value of `name` in `saved`, unless already saved by an earlier
+    /// activation. Saved values are usually `+value`, or `-` when the variable was not set.
+    fn save(&self, _w: &mut dyn io::Write, _name: &str, _saved: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Restores `name` from `saved` and removes `saved`.
+    fn restore(&self, _w: &mut dyn io::Write, _name: &str, _saved: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn file_name(&self) -> String {
+        format!("env.{}", self.extension())
+    }
+
+    /// Script restoring the environment saved by the one at `file_name`, if the format
+    /// needs one.
+    fn deactivate_file_name(&self) -> Option<String> {
+        Some(format!("deactivate.{}", self.extension()))
+    }
+}
+
+/// Name of the variable keeping the value `name` had before activation.
+pub fn saved_name(name: &str) -> String {
+    format!("CONAN_BUILD_OLD_{name}")
+}
+
+pub struct Sh;
+impl ShellFormat for Sh {
+    fn name(&self) -> &'static str {
+        "sh"
+    }
+
+    fn extension(&self) -> &'static str {
+        "sh"
+    }
+
+    fn path_separator(&self) -> char {
+        ':'
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "export {name}={}", sh_quote(value))
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "export {name}={}\"${{{name}:+:${name}}}\"",
+            sh_quote(value)
+        )
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "export {name}=\"${{{name}:+${name}:}}\"{}",
+            sh_quote(value)
+        )
+    }
+
+    fn save(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if [ -z \"${{{saved}+x}}\" ]; then {saved}=\"${{{name}++${name}}}\"; export {saved}=\"${{{saved}:--}}\"; fi"
+        )
+    }
+
+    fn restore(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if [ -n \"${{{saved}+x}}\" ]; then case \"${saved}\" in +*) export {name}=\"${{{saved}#+}}\" ;; *) unset {name} ;; esac; unset {saved}; fi"
+        )
+    }
+}
+
+/// direnv's `.envrc`, a bash script reloaded when the watched build infos change.
+pub struct Envrc;
+impl ShellFormat for Envrc {
+    fn name(&self) -> &'static str {
+        "envrc"
+    }
+
+    fn extension(&self) -> &'static str {
+        "envrc"
+    }
+
+    fn path_separator(&self) -> char {
+        Sh.path_separator()
+    }
+
+    fn watch(&self, w: &mut dyn io::Write, path: &str) -> io::Result<()> {
+        writeln!(w, "watch_file {}", sh_quote(path))
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        Sh.set(w, name, value)
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        Sh.prepend(w, name, value)
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        Sh.append(w, name, value)
+    }
+
+    fn file_name(&self) -> String {
+        ".envrc".to_string()
+    }
+
+    /// direnv restores the environment by itself when leaving the directory.
+    fn deactivate_file_name(&self) -> Option<String> {
+        None
+    }
+}
+
+pub struct Fish;
+impl ShellFormat for Fish {
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    fn extension(&self) -> &'static str {
+        "fish"
+    }
+
+    fn path_separator(&self) -> char {
+        ':'
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set -gx {name} {}", fish_quote(value))
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set -gx --path {name} {} ${name}", fish_quote(value))
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set -gx --path {name} ${name} {}", fish_quote(value))
+    }
+
+    fn save(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if not set -q {saved}; if set -q {name}; set -gx {saved} \"+${name}\"; else; set -gx {saved} -; end; end"
+        )
+    }
+
+    fn restore(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if set -q {saved}; if test \"${saved}\" = -; set -e {name}; else; set -gx {name} (string sub -s 2 -- \"${saved}\"); end; set -e {saved}; end"
+        )
+    }
+}
+
+/// `KEY=VALUE` lines as read by IDEs and dotenv libraries. Prepending relies on `${KEY}`
+/// expansion, which most of them support.
+pub struct Dotenv;
+impl ShellFormat for Dotenv {
+    fn name(&self) -> &'static str {
+        "dotenv"
+    }
+
+    fn extension(&self) -> &'static str {
+        "env"
+    }
+
+    fn path_separator(&self) -> char {
+        match cfg!(windows) {
+            true => ';',
+            false => ':',
+        }
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "{name}=\"{}\"", dotenv_escape(value))
+    }
+
+    // An empty entry left by an unset variable would make the loader search the working
+    // directory, so the separator is only added when the variable is set
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        let separator = self.path_separator();
+        let value = dotenv_escape(value);
+        writeln!(w, "{name}=\"{value}${{{name}:+{separator}${name}}}\"")
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        let separator = self.path_separator();
+        let value = dotenv_escape(value);
+        writeln!(w, "{name}=\"${{{name}:+${name}{separator}}}{value}\"")
+    }
+
+    fn file_name(&self) -> String {
+        ".env".to_string()
+    }
+
+    fn deactivate_file_name(&self) -> Option<String> {
+        None
+    }
+}
+
+pub struct Nushell;
+impl ShellFormat for Nushell {
+    fn name(&self) -> &'static str {
+        "nu"
+    }
+
+    fn extension(&self) -> &'static str {
+        "nu"
+    }
+
+    fn path_separator(&self) -> char {
+        match cfg!(windows) {
+            true => ';',
+            false => ':',
+        }
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "$env.{name} = {}", nu_quote(value))
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "$env.{name} = ($env.{name}? | default [] | split row (char esep) | prepend ({} | split row (char esep)) | str join (char esep))",
+            nu_quote(value)
+        )
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "$env.{name} = ($env.{name}? | default [] | split row (char esep) | append ({} | split row (char esep)) | str join (char esep))",
+            nu_quote(value)
+        )
+    }
+
+    fn save(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if '{saved}' not-in $env {{ $env.{saved} = (if '{name}' in $env {{ [$env.{name}] | flatten | str join (char esep) | $\"+($in)\" }} else {{ \"-\" }}) }}"
+        )
+    }
+
+    fn restore(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if '{saved}' in $env {{ if $env.{saved} == \"-\" {{ hide-env -i {name} }} else {{ $env.{name} = ($env.{saved} | str substring 1..) }}; hide-env {saved} }}"
+        )
+    }
+}
+
+pub struct Cmd;
+impl ShellFormat for Cmd {
+    fn name(&self) -> &'static str {
+        "cmd"
+    }
+
+    fn extension(&self) -> &'static str {
+        "bat"
+    }
+
+    fn path_separator(&self) -> char {
+        ';'
+    }
+
+    fn header(&self, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "@echo off")
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set \"{name}={}\"", cmd_escape(value)?)
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set \"{name}={};%{name}%\"", cmd_escape(value)?)
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "set \"{name}=%{name}%;{}\"", cmd_escape(value)?)
+    }
+
+    // Parenthesized blocks and comparisons of values are avoided, as values such as
+    // "Program Files (x86)" would end them. An unset variable is marked by `{saved}_UNSET`.
+    fn save(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        let unset = format!("{saved}_UNSET");
+        writeln!(
+            w,
+            "if not defined {saved} if not defined {unset} if defined {name} set \"{saved}=+%{name}%\""
+        )?;
+        writeln!(
+            w,
+            "if not defined {saved} if not defined {unset} set \"{unset}=1\""
+        )
+    }
+
+    fn restore(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        let unset = format!("{saved}_UNSET");
+        writeln!(w, "if defined {unset} set \"{name}=\"")?;
+        writeln!(w, "if defined {saved} set \"{name}=%{saved}:~1%\"")?;
+        writeln!(w, "set \"{saved}=\"")?;
+        writeln!(w, "set \"{unset}=\"")
+    }
+}
+
+pub struct PowerShell;
+impl ShellFormat for PowerShell {
+    fn name(&self) -> &'static str {
+        "ps1"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ps1"
+    }
+
+    fn path_separator(&self) -> char {
+        ';'
+    }
+
+    fn set(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(w, "$env:{name}={}", ps_quote(value))
+    }
+
+    fn prepend(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "$env:{name}={} + $env:{name}",
+            ps_quote(&format!("{value};"))
+        )
+    }
+
+    fn append(&self, w: &mut dyn io::Write, name: &str, value: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "$env:{name}=$env:{name} + {}",
+            ps_quote(&format!(";{value}"))
+        )
+    }
+
+    fn save(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if (-not (Test-Path env:{saved})) {{ $env:{saved} = if (Test-Path env:{name}) {{ \"+$env:{name}\" }} else {{ \"-\" }} }}"
+        )
+    }
+
+    fn restore(&self, w: &mut dyn io::Write, name: &str, saved: &str) -> io::Result<()> {
+        writeln!(
+            w,
+            "if (Test-Path env:{saved}) {{ if ($env:{saved} -eq \"-\") {{ Remove-Item env:{name} -ErrorAction SilentlyContinue }} else {{ $env:{name} = $env:{saved}.Substring(1) }}; Remove-Item env:{saved} }}"
+        )
+    }
+}
+
+pub const ALL_FORMATS: [&dyn ShellFormat; 7] =
+    [&Sh, &Envrc, &Dotenv, &Fish, &Nushell, &Cmd, &PowerShell];
+
+pub const DEFAULT_FORMATS: [&dyn ShellFormat; 2] = [&Sh, &PowerShell];
+
+pub fn shell_format(name: &str) -> Option<&'static dyn ShellFormat> {
+    ALL_FORMATS.into_iter().find(|format| {
+        format.name() == name
+            || format.extension() == name
+            || (name == "bash" && format.name() == "sh")
+    })
+}
+
+/// `C:\dir` as `/c/dir`, other paths as they are.
+fn posix_path(path: &str) -> Cow<'_, str> {
+    let mut chars = path.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(drive), Some(':'), Some('\\' | '/')) if drive.is_ascii_alphabetic() => {
+            Cow::Owned(format!(
+                "/{}{}",
+                drive.to_ascii_lowercase(),
+                path[2..].replace('\\', "/")
+            ))
+        }
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Single quotes, within which sh takes everything literally but the closing quote.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn nu_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Escapes for double quotes, where dotenv parsers expand `${VAR}` and backslash escapes.
+fn dotenv_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' | '\\' | '$' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Within `set "NAME=value"` only `%` is special in a batch file, line breaks cannot be
+/// written at all.
+fn cmd_escape(value: &str) -> io::Result<String> {
+    if value.contains(['\n', '\r']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{value:?} contains a line break, which cmd cannot set"),
+        ));
+    }
+
+    Ok(value.replace('%', "%%"))
+}
+
+/// PowerShell also ends single quoted strings on the typographic single quotes.
+fn ps_quote(value: &str) -> String {
+    let mut quoted = String::from('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::process::Command;
+
+    const SPECIAL: &[char] = &[
+        '\'',
+        '"',
+        '\\',
+        '$',
+        '`',
+        '%',
+        '!',
+        '*',
+        '?',
+        ';',
+        '&',
+        '|',
+        '(',
+        ')',
+        '<',
+        '>',
+        '{',
+        '}',
+        '[',
+        ']',
+        '#',
+        '~',
+        '=',
+        ':',
+        ' ',
+        '\t',
+        '\n',
+        '\r',
+        '^',
+        '@',
+        '\u{2018}',
+        '\u{2019}',
+        '\u{201a}',
+        '\u{201b}',
+        '\u{201c}',
+        '\u{e9}',
+        '\u{1f600}',
+    ];
+
+    /// Deterministic strings mixing letters with characters special to some shell.
+    fn samples(count: usize) -> Vec<String> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut samples = vec![String::new(), "plain".to_string(), "'".to_string()];
+        while samples.len() < count {
+            let len = next() % 24;
+            let sample = (0..len)
+                .map(|_| match next() % 3 {
+                    0 => (b'a' + (next() % 26) as u8) as char,
+                    _ => SPECIAL[next() as usize % SPECIAL.len()],
+                })
+                .collect();
+            samples.push(sample);
+        }
+        samples
+    }
+
+    /// Output of `program args... script`, or `None` when the shell is not installed, or
+    /// only in `PATH` directories that cannot be searched.
+    fn run(program: &str, args: &[&str], script: &str) -> Option<String> {
+        let output = match Command::new(program).args(args).arg(script).output() {
+            Ok(output) => output,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                ) =>
+            {
+                return None
+            }
+            Err(e) => panic!("Failure running {program}: {e}"),
+        };
+        assert!(
+            output.status.success(),
+            "{program} failed on {script:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    /// Reports a shell that is not installed, failing when `CONAN_BUILD_TEST_SHELLS=all`
+    /// asks for every round trip to run.
+    fn skip(program: &str) {
+        match std::env::var("CONAN_BUILD_TEST_SHELLS").as_deref() {
+            Ok("all") => panic!("{program} is not installed"),
+            // Written directly, as the test harness captures eprintln
+            _ => {
+                let message = format!("Skipping the {program} round trip, it is not installed\n");
+                let _ = io::Write::write_all(&mut io::stderr(), message.as_bytes());
+            }
+        }
+    }
+
+    fn round_trip(program: &str, args: &[&str], print: impl Fn(&str) -> String) {
+        for sample in samples(200) {
+            match run(program, args, &print(&sample)) {
+                Some(output) => assert_eq!(output, sample, "{program} changed the value"),
+                None => return skip(program),
+            }
+        }
+    }
+
+    #[test]
+    fn sh_quote_round_trips() {
+        round_trip("sh", &["-c"], |value| {
+            format!("printf '%s' {}", sh_quote(value))
+        });
+    }
+
+    #[test]
+    fn fish_quote_round_trips() {
+        round_trip("fish", &["-c"], |value| {
+            format!("printf '%s' {}", fish_quote(value))
+        });
+    }
+
+    #[test]
+    fn nu_quote_round_trips() {
+        round_trip("nu", &["--no-config-file", "-c"], |value| {
+            format!("print -n {}", nu_quote(value))
+        });
+    }
+
+    #[test]
+    fn ps_quote_round_trips() {
+        round_trip(
+            "pwsh",
+            &["-NoProfile", "-NonInteractive", "-Command"],
+            |value| format!("[Console]::Out.Write({})", ps_quote(value)),
+        );
+    }
+
+    // cmd cannot set line breaks nor an empty value, and prints in the console code page
+    #[test]
+    fn cmd_set_round_trips() {
+        let dir = TempDir::new("cmd-round-trip");
+        let samples = samples(200).into_iter().filter(|sample| {
+            !sample.is_empty() && sample.is_ascii() && !sample.contains(['\n', '\r'])
+        });
+        for sample in samples {
+            let script = written(|w| {
+                Cmd.header(w)?;
+                Cmd.set(w, "CONAN_BUILD_ROUND_TRIP", &sample)?;
+                writeln!(w, "set CONAN_BUILD_ROUND_TRIP")
+            });
+            let path = dir.write("round-trip.bat", &script);
+            match run("cmd", &["/d", "/c"], path.to_str().unwrap()) {
+                Some(output) => assert_eq!(
+                    output.trim_end_matches(['\r', '\n']),
+                    format!("CONAN_BUILD_ROUND_TRIP={sample}"),
+                    "cmd changed the value"
+                ),
+                None => return skip("cmd"),
+            }
+        }
+    }
+
+    #[test]
+    fn writes_each_format() {
+        let expected = [
+            (
+                &Sh as &dyn ShellFormat,
+                r#"export X='it'\''s "a" $b'
+export PATH='/opt/bin'"${PATH:+:$PATH}"
+export PATH="${PATH:+$PATH:}"'/opt/bin'
+"#,
+            ),
+            (
+                &Envrc,
+                r#"export X='it'\''s "a" $b'
+export PATH='/opt/bin'"${PATH:+:$PATH}"
+export PATH="${PATH:+$PATH:}"'/opt/bin'
+"#,
+            ),
+            (
+                &Fish,
+                r#"set -gx X 'it\'s "a" $b'
+set -gx --path PATH '/opt/bin' $PATH
+set -gx --path PATH $PATH '/opt/bin'
+"#,
+            ),
+            (
+                &Cmd,
+                r#"set "X=it's "a" $b"
+set "PATH=/opt/bin;%PATH%"
+set "PATH=%PATH%;/opt/bin"
+"#,
+            ),
+            (
+                &PowerShell,
+                r#"$env:X='it''s "a" $b'
+$env:PATH='/opt/bin;' + $env:PATH
+$env:PATH=$env:PATH + ';/opt/bin'
+"#,
+            ),
+        ];
+        for (format, expected) in expected {
+            let script = written(|w| {
+                format.set(w, "X", "it's \"a\" $b")?;
+                format.prepend(w, "PATH", "/opt/bin")?;
+                format.append(w, "PATH", "/opt/bin")
+            });
+            assert_eq!(script, expected, "{}", format.name());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_unix_formats() {
+        let expected = [
+            (
+                &Dotenv as &dyn ShellFormat,
+                r#"X="it's \"a\" \$b"
+PATH="/opt/bin${PATH:+:$PATH}"
+PATH="${PATH:+$PATH:}/opt/bin"
+"#,
+            ),
+            (
+                &Nushell,
+                r#"$env.X = "it's \"a\" $b"
+$env.PATH = ($env.PATH? | default [] | split row (char esep) | prepend ("/opt/bin" | split row (char esep)) | str join (char esep))
+$env.PATH = ($env.PATH? | default [] | split row (char esep) | append ("/opt/bin" | split row (char esep)) | str join (char esep))
+"#,
+            ),
+        ];
+        for (format, expected) in expected {
+            let script = written(|w| {
+                format.set(w, "X", "it's \"a\" $b")?;
+                format.prepend(w, "PATH", "/opt/bin")?;
+                format.append(w, "PATH", "/opt/bin")
+            });
+            assert_eq!(script, expected, "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(sh_quote("it's $HOME"), "'it'\\''s $HOME'");
+        assert_eq!(fish_quote("it's C:\\"), "'it\\'s C:\\\\'");
+        assert_eq!(nu_quote("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(
+            ps_quote("it's \u{2019}x\u{2019}"),
+            "'it''s \u{2019}\u{2019}x\u{2019}\u{2019}'"
+        );
+    }
+
+    #[test]
+    fn dotenv_escape_round_trips() {
+        fn unescape(escaped: &str) -> String {
+            let mut value = String::new();
+            let mut chars = escaped.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next().unwrap() {
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        c => value.push(c),
+                    },
+                    '"' | '$' | '\n' | '\r' => panic!("{c:?} left unescaped in {escaped:?}"),
+                    c => value.push(c),
+                }
+            }
+            value
+        }
+
+        for sample in samples(500) {
+            assert_eq!(unescape(&dotenv_escape(&sample)), sample);
+        }
+    }
+
+    fn written(write: impl FnOnce(&mut dyn io::Write) -> io::Result<()>) -> String {
+        let mut script = Vec::new();
+        write(&mut script).unwrap();
+        String::from_utf8(script).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dotenv_skips_the_separator_of_unset_lists() {
+        let prepend = written(|w| Dotenv.prepend(w, "LD_LIBRARY_PATH", "/opt/lib"));
+        assert_eq!(
+            prepend,
+            "LD_LIBRARY_PATH=\"/opt/lib${LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}\"\n"
+        );
+        let append = written(|w| Dotenv.append(w, "LD_LIBRARY_PATH", "/opt/lib"));
+        assert_eq!(
+            append,
+            "LD_LIBRARY_PATH=\"${LD_LIBRARY_PATH:+$LD_LIBRARY_PATH:}/opt/lib\"\n"
+        );
+
+        // Same expansion rules as sh
+        for (old, expected) in [("", "/opt/lib"), ("/usr/lib", "/opt/lib:/usr/lib")] {
+            let script =
+                format!("LD_LIBRARY_PATH='{old}'; {prepend}printf '%s' \"$LD_LIBRARY_PATH\"");
+            assert_eq!(run("sh", &["-c"], &script).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn cmd_escape_doubles_percent() {
+        assert_eq!(cmd_escape("100% %PATH%").unwrap(), "100%% %%PATH%%");
+        assert_eq!(cmd_escape("a&b|c\"d").unwrap(), "a&b|c\"d");
+    }
+
+    #[test]
+    fn cmd_escape_rejects_line_breaks() {
+        for value in ["a\nb", "a\rb", "\r\n"] {
+            let error = cmd_escape(value).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn converts_windows_paths_for_colon_lists() {
+        assert_eq!(posix_path("C:\\conan\\bin"), "/c/conan/bin");
+        assert_eq!(posix_path("d:/conan/lib"), "/d/conan/lib");
+        assert_eq!(posix_path("/usr/lib"), "/usr/lib");
+        assert_eq!(posix_path("C:"), "C:");
+        assert_eq!(Sh.path_entry("C:\\bin"), "/c/bin");
+        assert_eq!(PowerShell.path_entry("C:\\bin"), "C:\\bin");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{BuildInfo, Link};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    Always,
+    Static,
+    Shared,
+}
+impl When {
+    fn matches(self, link: Option<Link>) -> bool {
+        match self {
+            When::Always => true,
+            When::Static => link == Some(Link::Static),
+            When::Shared => link == Some(Link::Shared),
+        }
+    }
+}
+
+/// An environment variable read by a sys crate. The value is a template over the package
+/// paths: `{rootpath}`, `{libdir}`, `{bindir}`, `{includedir}`, `{version}` and `{exe}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysEnvVar {
+    pub name: String,
+    pub template: String,
+    pub when: When,
+    /// Resolved against the build context, for tools run by build scripts.
+    pub tool: bool,
+}
+
+/// Environment variables to set for each Conan package so that sys crates find it.
+#[derive(Debug, Clone, Default)]
+pub struct SysEnvRegistry {
+    packages: BTreeMap<String, Vec<SysEnvVar>>,
+}
+impl SysEnvRegistry {
+    pub fn new() -> SysEnvRegistry {
+        SysEnvRegistry::default()
+    }
+
+    pub fn builtin() -> SysEnvRegistry {
+        let mut registry = SysEnvRegistry::new();
+        registry
+            .register("openssl", "OPENSSL_DIR", "{rootpath}")
+            .register_when("openssl", "OPENSSL_STATIC", "1", When::Static)
+            .register_when("openssl", "OPENSSL_STATIC", "0", When::Shared)
+            .register_when("zlib", "LIBZ_SYS_STATIC", "1", When::Static)
+            .register("sqlite3", "SQLITE3_LIB_DIR", "{libdir}")
+            .register("sqlite3", "SQLITE3_INCLUDE_DIR", "{includedir}")
+            .register_when("sqlite3", "SQLITE3_STATIC", "1", When::Static)
+            .register("libsodium", "SODIUM_LIB_DIR", "{libdir}")
+            .register_when("libsodium", "SODIUM_SHARED", "1", When::Shared)
+            .register("libpq", "PQ_LIB_DIR", "{libdir}")
+            .register_when("xz_utils", "LZMA_API_STATIC", "1", When::Static)
+            .register_tool("protobuf", "PROTOC", "{bindir}/protoc{exe}")
+            .register_tool("protobuf", "PROTOC_INCLUDE", "{includedir}");
+        for package in ["clang", "libclang", "llvm"] {
+            registry.register_tool(package, "LIBCLANG_PATH", "{libdir}");
+        }
+
+        registry
+    }
+
+    pub fn register(&mut self, package: &str, name: &str, template: &str) -> &mut Self {
+        self.insert(package, name, template, When::Always, false)
+    }
+
+    pub fn register_when(
+        &mut self,
+        package: &str,
+        name: &str,
+        template: &str,
+        when: When,
+    ) -> &mut Self {
+        self.insert(package, name, template, when, false)
+    }
+
+    pub fn register_tool(&mut self, package: &str, name: &str, template: &str) -> &mut Self {
+        self.insert(package, name, template, When::Always, true)
+    }
+
+    /// Adds a variable, replacing any registered for the same package, name and condition.
+    pub fn insert(
+        &mut self,
+        package: &str,
+        name: &str,
+        template: &str,
+        when: When,
+        tool: bool,
+    ) -> &mut Self {
+        let vars = self.packages.entry(package.to_string()).or_default();
+        vars.retain(|var| var.name != name || var.when != when);
+        vars.push(SysEnvVar {
+            name: name.to_string(),
+            template: template.to_string(),
+            when,
+            tool,
+        });
+        self
+    }
+
+    pub fn remove(&mut self, package: &str) -> Option<Vec<SysEnvVar>> {
+        self.packages.remove(package)
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.packages.keys().map(String::as_str)
+    }
+
+    pub fn vars(&self, package: &str) -> &[SysEnvVar] {
+        self.packages
+            .get(package)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Values of the variables registered for `package`. Tool variables are resolved from
+    /// `build`, the others from `host`. Variables referring to a missing path, and tool
+    /// variables without a build context, are skipped.
+    pub fn resolve(
+        &self,
+        host: &BuildInfo,
+        build: Option<&BuildInfo>,
+        package: &str,
+        link: Option<Link>,
+    ) -> Vec<(String, String)> {
+        self.vars(package)
+            .iter()
+            .filter(|var| var.when.matches(link))
+            .filter_map(|var| {
+                let info = match var.tool {
+                    true => build?,
+                    false => host,
+                };
+                info.try_package(package)?;
+                let value = expand(&var.template, info, package)?;
+                Some((var.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+fn expand(template: &str, info: &BuildInfo, package: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .unwrap_or_else(|| panic!("Unclosed placeholder in {template:?}"));
+        value.push_str(&rest[..start]);
+
+        let key = &rest[start + 1..start + end];
+        let replacement = match key {
+            "rootpath" => info.rootpath_for(package).to_string(),
+            "libdir" => info.libdir_for(package).first()?.to_string(),
+            "bindir" => info.bindir_for(package).first()?.to_string(),
+            "includedir" => info.includes_for(package).first()?.to_string(),
+            "version" => info.version_for(package).to_string(),
+            "exe" => match info.os() {
+                "Windows" => ".exe".to_string(),
+                _ => String::new(),
+            },
+            _ => panic!("Unknown placeholder {{{key}}} in {template:?}"),
+        };
+        value.push_str(&replacement);
+        rest = &rest[start + end + 1..];
+    }
+    value.push_str(rest);
+
+    Some(value)
+}
@@ -0,0 +1,438 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A Conan package version. Unlike semver it may have any number of dot separated items,
+/// which may be alphanumeric such as `1.1.1t` or `cci.20230101`.
+#[derive(Debug, Clone)]
+pub struct Version {
+    text: String,
+    items: Vec<Item>,
+    pre: Option<Vec<Item>>,
+}
+impl Version {
+    pub fn parse(text: &str) -> Version {
+        let text = text.trim();
+        let version = text
+            .split_once('+')
+            .map_or(text, |(version, _build)| version);
+        let (main, pre) = match version.split_once('-') {
+            Some((main, pre)) => (main, Some(Item::parse_all(pre))),
+            None => (version, None),
+        };
+
+        Version {
+            text: text.to_string(),
+            items: Item::parse_all(main),
+            pre,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    fn bump(&self, position: usize) -> Version {
+        let mut items = self.items[..=position].to_vec();
+        items[position] = match &items[position] {
+            Item {
+                number: Some(number),
+                ..
+            } => Item::number(number + 1),
+            item => Item {
+                number: None,
+                text: format!("{}~", item.text),
+            },
+        };
+        let text = items
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+
+        Version {
+            items,
+            pre: Some(Vec::new()),
+            text,
+        }
+    }
+}
+impl FromStr for Version {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Version::parse(s))
+    }
+}
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Version {}
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.items.len().max(other.items.len());
+        let zero = Item::number(0);
+        let items = (0..len).map(|i| {
+            let a = self.items.get(i).unwrap_or(&zero);
+            let b = other.items.get(i).unwrap_or(&zero);
+            a.cmp(b)
+        });
+
+        let pre = match (&self.pre, &other.pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => a.cmp(b),
+        };
+
+        items
+            .chain([pre])
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// One dot separated item of a version, split into its numeric prefix and the remainder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    number: Option<u64>,
+    text: String,
+}
+impl Item {
+    fn number(number: u64) -> Item {
+        Item {
+            number: Some(number),
+            text: String::new(),
+        }
+    }
+
+    fn parse_all(text: &str) -> Vec<Item> {
+        text.split('.').map(Item::parse).collect()
+    }
+
+    fn parse(text: &str) -> Item {
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, text) = text.split_at(digits);
+
+        Item {
+            number: number.parse().ok(),
+            text: text.to_string(),
+        }
+    }
+}
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Item {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let number = match (self.number, other.number) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+
+        number.then_with(|| self.text.cmp(&other.text))
+    }
+}
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(number) = self.number {
+            write!(f, "{number}")?;
+        }
+        f.write_str(&self.text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    op: Op,
+    version: Version,
+}
+impl Condition {
+    fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Vec<Condition>, String> {
+        let condition = |op, version: &str| Condition {
+            op,
+            version: Version::parse(version),
+        };
+        let ops = [
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+            ("=", Op::Eq),
+        ];
+
+        if text == "*" {
+            return Ok(Vec::new());
+        }
+        if let Some(version) = text.strip_prefix('~') {
+            let version = Version::parse(version);
+            let position = 1.min(version.items.len() - 1);
+            let upper = version.bump(position);
+            return Ok(vec![
+                Condition {
+                    op: Op::Ge,
+                    version,
+                },
+                Condition {
+                    op: Op::Lt,
+                    version: upper,
+                },
+            ]);
+        }
+        if let Some(version) = text.strip_prefix('^') {
+            let version = Version::parse(version);
+            let position = version
+                .items
+                .iter()
+                .position(|item| item.number != Some(0))
+                .unwrap_or(version.items.len() - 1);
+            let upper = version.bump(position);
+            return Ok(vec![
+                Condition {
+                    op: Op::Ge,
+                    version,
+                },
+                Condition {
+                    op: Op::Lt,
+                    version: upper,
+                },
+            ]);
+        }
+
+        for (prefix, op) in ops {
+            if let Some(version) = text.strip_prefix(prefix) {
+                let version = version.trim();
+                if version.is_empty() {
+                    return Err(format!("missing version after {prefix:?}"));
+                }
+                return Ok(vec![condition(op, version)]);
+            }
+        }
+
+        Ok(vec![condition(Op::Eq, text)])
+    }
+}
+
+/// A version range such as `>=4.3, <4.4`, also accepting Conan's `[>=4.3 <4.4]` syntax,
+/// `~`, `^`, `*` and alternatives separated by `||`.
+#[derive(Debug, Clone)]
+pub struct VersionReq {
+    text: String,
+    alternatives: Vec<Vec<Condition>>,
+}
+impl VersionReq {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|conditions| {
+            conditions
+                .iter()
+                .all(|condition| condition.matches(version))
+        })
+    }
+}
+impl FromStr for VersionReq {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let range = text
+            .strip_prefix('[')
+            .and_then(|range| range.strip_suffix(']'))
+            .unwrap_or(text);
+
+        let alternatives = range
+            .split("||")
+            .map(|alternative| {
+                let mut conditions = Vec::new();
+                let mut pending_op = None::<&str>;
+                let tokens = alternative
+                    .split([',', ' '])
+                    .map(str::trim)
+                    .filter(|token| !token.is_empty())
+                    .collect::<Vec<_>>();
+                if tokens.is_empty() {
+                    return Err("empty requirement".to_string());
+                }
+                for token in tokens {
+                    let token = match pending_op.take() {
+                        Some(op) => format!("{op}{token}"),
+                        None if token.chars().all(|c| "<>=!~^".contains(c)) => {
+                            pending_op = Some(token);
+                            continue;
+                        }
+                        None => token.to_string(),
+                    };
+                    conditions.extend(Condition::parse(&token)?);
+                }
+
+                match pending_op {
+                    Some(op) => Err(format!("missing version after {op:?}")),
+                    None => Ok(conditions),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid version requirement {text:?}: {e}"))?;
+
+        Ok(VersionReq {
+            text: text.to_string(),
+            alternatives,
+        })
+    }
+}
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(text: &str) -> Version {
+        Version::parse(text)
+    }
+
+    fn req(text: &str) -> VersionReq {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn orders_numeric_items() {
+        assert!(v("1.2.10") > v("1.2.9"));
+        assert!(v("4.3") < v("4.3.1"));
+        assert_eq!(v("4.3"), v("4.3.0"));
+        assert!(v("10") > v("9.9.9"));
+    }
+
+    #[test]
+    fn orders_alphanumeric_items() {
+        assert!(v("1.1.1t") > v("1.1.1s"));
+        assert!(v("1.1.1t") > v("1.1.1"));
+        assert!(v("1.1.1t") < v("1.1.2"));
+        assert!(v("3.0.10") > v("1.1.1w"));
+        assert!(v("cci.20230101") > v("cci.20220430"));
+        assert_eq!(v("cci.20230101").as_str(), "cci.20230101");
+    }
+
+    #[test]
+    fn orders_pre_releases_before_releases() {
+        assert!(v("1.0.0-rc1") < v("1.0.0"));
+        assert!(v("1.0.0-alpha") < v("1.0.0-beta"));
+        assert!(v("1.0.0-rc.2") < v("1.0.0-rc.10"));
+        assert!(v("1.0.0-rc1") > v("0.9.9"));
+    }
+
+    #[test]
+    fn ignores_build_metadata() {
+        assert_eq!(v("1.2.3+build.5"), v("1.2.3"));
+        assert_eq!(v("1.2.3+build.5").to_string(), "1.2.3+build.5");
+    }
+
+    #[test]
+    fn matches_comparison_ranges() {
+        let range = req(">=4.3, <4.4");
+        assert!(range.matches(&v("4.3.4")));
+        assert!(range.matches(&v("4.3")));
+        assert!(!range.matches(&v("4.4.0")));
+        assert!(!range.matches(&v("4.2.9")));
+
+        assert!(req(">= 1.2 < 2").matches(&v("1.5")));
+        assert!(req("!=1.2").matches(&v("1.3")));
+        assert!(!req("!=1.2").matches(&v("1.2.0")));
+        assert!(req("1.2.3").matches(&v("1.2.3")));
+        assert!(req("=1.2.3").matches(&v("1.2.3")));
+        assert!(req("==1.2.3").matches(&v("1.2.3")));
+    }
+
+    #[test]
+    fn matches_conan_bracket_syntax() {
+        let range = req("[>=1.1.1 <3]");
+        assert!(range.matches(&v("1.1.1t")));
+        assert!(range.matches(&v("3.0.0-rc1")));
+        assert!(!range.matches(&v("3.0.0")));
+        assert!(req("[*]").matches(&v("cci.20230101")));
+    }
+
+    #[test]
+    fn matches_tilde_bounds() {
+        let range = req("~1.2.3");
+        assert!(range.matches(&v("1.2.3")));
+        assert!(range.matches(&v("1.2.9")));
+        assert!(!range.matches(&v("1.3.0")));
+        assert!(!range.matches(&v("1.2.2")));
+
+        let range = req("~1");
+        assert!(range.matches(&v("1.9")));
+        assert!(!range.matches(&v("2.0")));
+    }
+
+    #[test]
+    fn matches_caret_bounds() {
+        let range = req("^1.2.3");
+        assert!(range.matches(&v("1.9.0")));
+        assert!(!range.matches(&v("2.0.0")));
+        assert!(!range.matches(&v("2.0.0-rc1")));
+
+        let range = req("^0.2.3");
+        assert!(range.matches(&v("0.2.9")));
+        assert!(!range.matches(&v("0.3.0")));
+    }
+
+    #[test]
+    fn matches_alternatives() {
+        let range = req("<1.0 || >=2.0");
+        assert!(range.matches(&v("0.9")));
+        assert!(range.matches(&v("2.1")));
+        assert!(!range.matches(&v("1.5")));
+    }
+
+    #[test]
+    fn rejects_invalid_requirements() {
+        assert!("".parse::<VersionReq>().is_err());
+        assert!("  ".parse::<VersionReq>().is_err());
+        assert!("[]".parse::<VersionReq>().is_err());
+        assert!(">=1.0 ||".parse::<VersionReq>().is_err());
+        assert!(">=".parse::<VersionReq>().is_err());
+        assert!(">= 1.0 <".parse::<VersionReq>().is_err());
+    }
+}
@@ -1,8 +1,3 @@
-use conan_build::Conan;
-
 fn main() {
-    let mut conan = Conan::new();
-    conan.depends_on(["zeromq"]);
-    conan.depends_on_optional(["libsodium"]);
-    conan.depends_on_libcxx();
+    conan_build::build();
 }